@@ -13,9 +13,9 @@
 //! applies the mask to transform the latest response into other API versions to satisfy backwards
 //! compatibility.
 //!
-//! This is an early build where the input validation is more flexible. Based on real world usage
-//! the first stable release will probably restrict to formal schema draft standards and/or allow
-//! you to restrict to specific ones.
+//! This is an early build where the input validation is flexible by default. Use [`MaskOptions`]
+//! with [`from_str_with_options`] or [`from_reader_with_options`] to restrict schemas to a specific
+//! draft, require `$schema`, and reject unknown keywords.
 //!
 //! # Examples
 //! - Use generate a mask with [`from_str`] or [`from_reader`] and apply it to a document.
@@ -42,7 +42,7 @@
 //!
 //!   let mask = from_str(schema).unwrap();
 //!   let masker = JsonMasker::new(mask);
-//!   masker.mask(&mut document);
+//!   masker.mask(&mut document).unwrap();
 //!
 //!   assert_eq!(r#"{"foo":1}"#, serde_json::to_string(&document).unwrap())
 //!   ```
@@ -51,8 +51,23 @@
 mod mask;
 
 pub use mask::from_reader;
+pub use mask::from_reader_with_options;
 pub use mask::from_str;
+pub use mask::from_str_with_options;
+pub use mask::BranchSelection;
+pub use mask::Direction;
+pub use mask::Draft;
+pub use mask::EnumMismatch;
+pub use mask::ExtraItems;
 pub use mask::JsonMasker;
 pub use mask::Mask;
+pub use mask::MaskError;
+pub use mask::MaskOptions;
+pub use mask::MaskReport;
+pub use mask::OutputError;
 pub use mask::ParseError;
+pub use mask::TypeMismatch;
+pub use mask::UnknownDiscriminator;
+pub use mask::UnknownKeywords;
+pub use mask::UnmatchedBranches;
 pub use mask::ValidJsonSchema;
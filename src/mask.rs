@@ -1,322 +1,5286 @@
-use jsonschema::JSONSchema;
+use fancy_regex::Regex;
+use jsonschema::paths::PathChunk;
+use jsonschema::primitive_type::PrimitiveType;
+use jsonschema::{CompilationOptions, JSONSchema, ValidationError};
 use serde_json::{Error, Map, Value};
 use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
 use thiserror::Error;
 
-#[derive(Default)]
+/// A mask compiled from a JSON Schema.
+///
+/// The nodes are stored in an arena and refer to each other by index, so a recursive schema
+/// compiles to a cycle in the graph instead of an infinitely deep tree.
 pub struct Mask {
-    pub properties: HashMap<String, Option<Mask>>,
+    nodes: Vec<MaskNode>,
+    root: NodeId,
+    // The schema compiled as a whole when it was validated, to validate the masked document.
+    validator: Arc<JSONSchema>,
+    compiler: SubschemaCompiler,
 }
 
-pub struct ValidJsonSchema(Value);
+type NodeId = usize;
 
-#[derive(Error, Debug)]
-pub enum ParseError {
-    #[error("serde json could not parse the invalid json")]
-    InvalidJson(#[from] Error),
-    #[error("the provided json was valid, but it wasn't a valid json schema")]
-    InvalidJsonSchema(String),
+#[derive(Default)]
+struct MaskNode {
+    // The JSON pointer of the schema the node was built from.
+    pointer: String,
+    // The declared types, empty when the schema allows any type.
+    types: Vec<PrimitiveType>,
+    // The values allowed by enum or const.
+    allowed_values: Option<Vec<Value>>,
+    // The replacements of values that aren't allowed, from x-enum-map and x-enum-fallback.
+    enum_map: HashMap<String, Value>,
+    enum_fallback: Option<Value>,
+    // The default value, used to fill a missing property.
+    default: Option<Value>,
+    // Only a node built from an object (or array) schema masks an object (or array) value, any
+    // other value is kept as is.
+    object: bool,
+    array: bool,
+    properties: HashMap<String, NodeId>,
+    // The names each property is read from when it's missing, from x-source-name and x-aliases.
+    renames: Vec<(String, Vec<String>)>,
+    // The JSON pointer in the document each property is moved from when it's missing, from
+    // x-source.
+    moves: Vec<(String, String)>,
+    required: Vec<String>,
+    pattern_properties: Vec<(Regex, NodeId)>,
+    // None when the schema doesn't have additionalProperties, in which case the keys it doesn't
+    // describe are left to unevaluatedProperties.
+    additional_properties: Option<Additional>,
+    unevaluated_properties: Option<Additional>,
+    prefix_items: Vec<NodeId>,
+    items: Option<NodeId>,
+    // Subschemas that apply to the same value as this node. The masker takes the union of every
+    // applicable node, so the branches don't need to be merged (which isn't possible while a
+    // recursive schema is still being built).
+    all_of: Vec<NodeId>,
+    // The anyOf and oneOf branches, which apply depending on the BranchSelection of the masker.
+    any_of: Vec<Branch>,
+    one_of: Vec<Branch>,
+    conditional: Option<Conditional>,
+    // Subschemas that apply when the object has the key.
+    dependent_schemas: Vec<(String, NodeId)>,
+    discriminator: Option<Discriminator>,
+    read_only: bool,
+    write_only: bool,
+    deprecated: bool,
+    // Built from the false schema, which no value matches, so the value is dropped.
+    drop: bool,
 }
 
-impl ValidJsonSchema {
-    pub fn new(schema: Value) -> Result<Self, ParseError> {
-        // JSONSchema will validate that the nested portion of a schema is valid, but if the root
-        // isn't then it will accept it anyway. This violates our invariants, so we need to check
-        // them explicitly at the root.
-        if !schema.is_object()
-            || !schema.as_object().unwrap().contains_key("type")
-            || !schema.as_object().unwrap().get("type").unwrap().is_string()
-        {
-            return Err(ParseError::InvalidJsonSchema(
-                "Invalid JSON Schema object".to_string(),
-            ));
-        }
+// What happens to the keys of an object that aren't described by the node.
+#[derive(Clone, Copy)]
+enum Additional {
+    Drop,
+    Keep,
+    Mask(NodeId),
+}
 
-        match JSONSchema::options().compile(&schema) {
-            Ok(_) => Ok(ValidJsonSchema(schema)),
-            Err(error) => Err(ParseError::InvalidJsonSchema(error.to_string())),
+impl Additional {
+    // Adds the mask to children, returns whether the value is kept as is.
+    fn apply(self, children: &mut Vec<NodeId>) -> bool {
+        match self {
+            Additional::Drop => false,
+            Additional::Keep => true,
+            Additional::Mask(child) => {
+                children.push(child);
+                false
+            }
         }
     }
 }
 
-pub fn from_str(json: &str) -> Result<Mask, ParseError> {
-    Ok(Mask::from(&ValidJsonSchema::new(serde_json::from_str::<
-        Value,
-    >(json)?)?))
+struct Branch {
+    node: NodeId,
+    // The validator is only compiled when a value is first validated against the branch, which
+    // doesn't happen at all with BranchSelection::Union.
+    pointer: String,
+    validator: OnceLock<JSONSchema>,
 }
 
-pub fn from_reader<R>(reader: R) -> Result<Mask, ParseError>
-where
-    R: std::io::Read,
-{
-    Ok(Mask::from(&ValidJsonSchema::new(
-        serde_json::from_reader::<R, Value>(reader)?,
-    )?))
+impl Branch {
+    fn new(node: NodeId, pointer: String) -> Self {
+        Branch {
+            node,
+            pointer,
+            validator: OnceLock::new(),
+        }
+    }
+
+    fn is_valid(&self, value: &Value, compiler: &SubschemaCompiler) -> Result<bool, MaskError> {
+        let validator = match self.validator.get() {
+            Some(validator) => validator,
+            None => {
+                let validator = compiler.compile(&self.pointer).map_err(|message| {
+                    MaskError::InvalidSubschema {
+                        pointer: self.pointer.clone(),
+                        message,
+                    }
+                })?;
+                self.validator.get_or_init(|| validator)
+            }
+        };
+
+        Ok(validator.is_valid(value))
+    }
 }
 
-fn parse_schema_node(mask: &mut Mask, schema: &Value) {
-    // unwrap is safe, because we only recurse for objects, and we validate that the provided json
-    // conforms to "json schema" schema (not a typo).
-    if let Some(properties) = schema.as_object().unwrap().get("properties") {
-        if let Some(properties) = properties.as_object() {
-            for (key, child) in properties {
-                let child_object = child.as_object().unwrap().get("type");
+// Compiles validators for the subschemas of a schema. The whole schema is registered as a
+// document, so references inside a subschema resolve the same way they do from the root.
+struct SubschemaCompiler {
+    base_uri: String,
+    draft: Option<Value>,
+    options: CompilationOptions,
+}
 
-                if child_object.is_some() && child_object.unwrap() == "object" {
-                    let mut child_mask = Mask::default();
-                    parse_schema_node(&mut child_mask, child);
+impl SubschemaCompiler {
+    fn new(root: &Value, options: &MaskOptions) -> Self {
+        let base_uri = root_uri(root).unwrap_or(DEFAULT_BASE_URI).to_string();
 
-                    mask.properties.insert(key.clone(), Some(child_mask));
-                } else {
-                    mask.properties.insert(key.clone(), None);
-                }
-            }
+        let mut compilation_options = options.compilation_options();
+        compilation_options.with_document(base_uri.clone(), root.clone());
+
+        SubschemaCompiler {
+            base_uri,
+            draft: root.get("$schema").cloned(),
+            options: compilation_options,
         }
     }
-}
-
-impl From<&ValidJsonSchema> for Mask {
-    fn from(value: &ValidJsonSchema) -> Self {
-        let mut mask = Mask::default();
 
-        if value
-            .0
-            .as_object()
-            .unwrap()
-            .get("type")
-            .unwrap()
-            .as_str()
-            .unwrap()
-            == "object"
-        {
-            parse_schema_node(&mut mask, &value.0);
+    fn compile(&self, pointer: &str) -> Result<JSONSchema, String> {
+        let mut subschema = Map::new();
+        if let Some(draft) = &self.draft {
+            subschema.insert("$schema".to_string(), draft.clone());
         }
+        subschema.insert(
+            "$ref".to_string(),
+            Value::String(format!("{}#{}", self.base_uri, encode_fragment(pointer))),
+        );
 
-        mask
+        self.options
+            .compile(&Value::Object(subschema))
+            .map_err(|error| error.to_string())
     }
 }
 
-pub struct JsonMasker {
-    mask: Mask,
+// An OpenAPI discriminator, which maps the value of a property to the branch that applies.
+struct Discriminator {
+    property: String,
+    mapping: HashMap<String, NodeId>,
 }
 
-impl JsonMasker {
-    pub fn new(mask: Mask) -> Self {
-        JsonMasker { mask }
+impl Discriminator {
+    fn select(&self, object: &Map<String, Value>) -> Option<NodeId> {
+        let value = object.get(&self.property)?.as_str()?;
+
+        self.mapping.get(value).copied()
     }
+}
 
-    pub fn mask(&self, object: &mut Value) {
-        if let Some(unwrapped_object) = object.as_object_mut() {
-            JsonMasker::mask_object(unwrapped_object, &self.mask)
+// The if, then and else subschemas. When the value matches the condition, the union includes the
+// condition itself and the then node, otherwise it includes the else node.
+struct Conditional {
+    condition: Branch,
+    then: Option<NodeId>,
+    otherwise: Option<NodeId>,
+}
+
+impl MaskNode {
+    // Adds the masks that apply to the value of the key to children. Returns None when the node
+    // doesn't evaluate the key, otherwise whether the value is kept as is when there are no masks.
+    fn property_masks(&self, key: &str, children: &mut Vec<NodeId>) -> Option<bool> {
+        let described = children.len();
+
+        children.extend(self.properties.get(key));
+
+        // A pattern that can't be evaluated (e.g. it exceeds the backtracking limit) is treated as
+        // not matching.
+        children.extend(
+            self.pattern_properties
+                .iter()
+                .filter(|(pattern, _)| pattern.is_match(key).unwrap_or(false))
+                .map(|(_, child)| *child),
+        );
+
+        // Additional properties only apply to the keys that aren't described by properties or
+        // pattern properties.
+        if children.len() > described {
+            return Some(false);
         }
+
+        Some(self.additional_properties?.apply(children))
     }
+}
 
-    fn mask_object(object: &mut Map<String, Value>, mask_node: &Mask) {
-        object.retain(|key, value| match mask_node.properties.get(key) {
-            None => false,
-            Some(mask_child_node) => {
-                if let Some(node) = value.as_object_mut() {
-                    if let Some(mask_child_node) = mask_child_node {
-                        JsonMasker::mask_object(node, mask_child_node)
-                    }
-                }
+impl Mask {
+    pub fn from_schema(
+        schema: &ValidJsonSchema,
+        options: &MaskOptions,
+    ) -> Result<Self, ParseError> {
+        SchemaParser::new(&schema.schema, options).parse(schema.validator.clone())
+    }
 
-                true
-            }
-        })
+    fn node(&self, id: NodeId) -> &MaskNode {
+        &self.nodes[id]
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde_json::json;
-    use std::str::FromStr;
-    use uuid::{uuid, Uuid};
+/// Options for validating a schema and building a [`Mask`] from it.
+#[derive(Clone, Debug, Default)]
+pub struct MaskOptions {
+    exclude_deprecated: bool,
+    draft: Option<Draft>,
+    require_schema_keyword: bool,
+    validate_formats: Option<bool>,
+    unknown_keywords: UnknownKeywords,
+}
 
-    fn get_masker(schema: &str) -> JsonMasker {
-        JsonMasker::new(Mask::from(&get_valid_schema(schema).unwrap()))
+impl MaskOptions {
+    /// Requires the schema to be of the draft. A schema that declares another draft with `$schema`
+    /// is rejected, and the draft is used instead of detecting it from `$schema`.
+    pub fn with_draft(mut self, draft: Draft) -> Self {
+        self.draft = Some(draft);
+        self
     }
 
-    fn get_valid_schema(schema: &str) -> Result<ValidJsonSchema, ParseError> {
-        ValidJsonSchema::new(serde_json::from_str(schema).unwrap())
+    /// When enabled, a schema that doesn't declare its draft with `$schema` is rejected.
+    pub fn with_require_schema_keyword(mut self, require_schema_keyword: bool) -> Self {
+        self.require_schema_keyword = require_schema_keyword;
+        self
     }
 
-    const NONCE: u64 = 12345;
-    const VM_ID: Uuid = uuid!("19e656a1-b9ca-4344-88c9-ef0a6b5999e5");
-    const FOO: &str = "foo-value";
-    const BAR: &str = "bar-value";
-    const CREATED_ON: &str = "2023-07-28 17:59:14Z";
-    const EXPIRES_ON: &str = "2023-07-28 20:59:14Z";
-
-    fn get_metadata_json() -> Value {
-        json!({
-            "nonce": 12345,
-            "vmId": VM_ID
-        })
+    /// Whether `format` is asserted, rather than only annotated, when validating values against
+    /// the schema. Defaults to the behavior of the draft.
+    pub fn with_validate_formats(mut self, validate_formats: bool) -> Self {
+        self.validate_formats = Some(validate_formats);
+        self
     }
 
-    fn get_foobar_json() -> Value {
-        json!({
-            "foo": FOO,
-            "bar": BAR
-        })
+    pub fn with_unknown_keywords(mut self, unknown_keywords: UnknownKeywords) -> Self {
+        self.unknown_keywords = unknown_keywords;
+        self
     }
 
-    fn get_mixed_json() -> Value {
-        json!({
-            "nonce": NONCE,
-            "foo": FOO
-        })
+    /// When enabled, properties the schema marks as `deprecated` aren't part of the mask, which
+    /// previews the next version of the schema.
+    pub fn with_exclude_deprecated(mut self, exclude_deprecated: bool) -> Self {
+        self.exclude_deprecated = exclude_deprecated;
+        self
     }
 
-    #[test]
-    // There are lots of unwraps in the the masker parsing because it expects a valid schema.
-    // This test ensures that the valid schema wrapper is holding these invariants correctly.
-    pub fn bad_schema_no_panic() {
-        assert!(get_valid_schema(INVALID_SCHEMA_OBJECT).is_err());
-        assert!(get_valid_schema(INVALID_NESTED_SCHEMA).is_err());
-        assert!(get_valid_schema(INVALID_SCHEMA_NULL_TYPE).is_err());
-        assert!(get_valid_schema(INVALID_SCHEMA_NULL_PROPERTIES).is_err());
-        assert!(get_valid_schema(RANDOM_JSON).is_err());
+    // The draft of the schema, either the required draft or the one it declares.
+    fn draft_of(&self, schema: &Value) -> Draft {
+        self.draft
+            .or_else(|| {
+                schema
+                    .get("$schema")
+                    .and_then(Value::as_str)
+                    .and_then(Draft::from_uri)
+            })
+            .unwrap_or(Draft::Draft7)
     }
 
-    #[test]
-    // Schema validator only checks that provided fields are valid, but missing information is
-    // allowed.
-    pub fn schema_missing_expected_fields_no_panic() {
-        get_masker(INVALID_SCHEMA_EMPTY_PROPERTIES);
-        get_masker(INVALID_SCHEMA_NO_TYPE);
-        get_masker(INVALID_SCHEMA_NO_PROPERTIES);
+    // The options to compile a schema with, so that a schema is validated the same way when it's
+    // validated as when its subschemas are matched against values.
+    fn compilation_options(&self) -> CompilationOptions {
+        let mut options = JSONSchema::options();
+
+        if let Some(draft) = self.draft {
+            options.with_draft(draft.into());
+        }
+        if let Some(validate_formats) = self.validate_formats {
+            options.should_validate_formats(validate_formats);
+        }
+
+        options
     }
+}
 
-    #[test]
-    pub fn mask_json_simple_schema_exact_match() {
-        let mut json = get_metadata_json();
+/// What [`JsonMasker::mask`] found in the document.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MaskReport {
+    /// The JSON pointers of the `deprecated` values, when enabled with
+    /// [`JsonMasker::with_report_deprecated`].
+    pub deprecated: Vec<String>,
+    /// The JSON pointers of the values that couldn't be converted to the type declared by the
+    /// schema, when enabled with [`JsonMasker::with_coerce_types`].
+    pub unconverted: Vec<String>,
+}
 
-        get_masker(SIMPLE_SCHEMA).mask(&mut json);
+/// How [`JsonMasker`] treats the elements of a tuple array beyond the positions described by
+/// `prefixItems` (or array form `items`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExtraItems {
+    /// Keep the extra elements as is.
+    Keep,
+    /// Remove the extra elements from the array.
+    Drop,
+    /// Apply the mask of the trailing `items` (or `additionalItems`) schema to the extra elements,
+    /// which removes them when the schema is `false`.
+    #[default]
+    Mask,
+}
 
-        assert_eq!(NONCE, json["nonce"].as_u64().unwrap());
-        assert_eq!(
-            VM_ID,
-            Uuid::from_str(json["vmId"].as_str().unwrap()).unwrap()
-        );
-    }
+/// How [`JsonMasker`] picks which `anyOf` and `oneOf` branches mask a value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BranchSelection {
+    /// Use the union of every branch, regardless of the value.
+    #[default]
+    Union,
+    /// Validate the value against each branch and only use the branches it matches.
+    Matching,
+}
 
-    #[test]
-    pub fn mask_json_simple_schema_all_filtered() {
-        let mut json = get_foobar_json();
+/// How [`JsonMasker`] treats a value that matches none of the `anyOf` or `oneOf` branches, or more
+/// than one `oneOf` branch, when using [`BranchSelection::Matching`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnmatchedBranches {
+    /// Use the union of every branch.
+    #[default]
+    Union,
+    /// Keep the value as is.
+    Keep,
+    /// Remove the value.
+    Drop,
+}
 
-        get_masker(SIMPLE_SCHEMA).mask(&mut json);
+/// How [`JsonMasker`] treats an object whose discriminator property doesn't map to a schema.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownDiscriminator {
+    /// Only keep the properties of the schema with the discriminator, none of its branches.
+    #[default]
+    KeepBase,
+    /// Remove the object.
+    Drop,
+    /// Fail with [`MaskError::UnknownDiscriminator`].
+    Error,
+}
 
-        assert!(json.get("foo").is_none());
-        assert!(json.get("bar").is_none());
-    }
+/// Whether [`JsonMasker`] masks a request or a response, which decides what happens to values the
+/// schema marks as `readOnly` or `writeOnly`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Direction {
+    /// Ignore `readOnly` and `writeOnly`.
+    #[default]
+    Any,
+    /// Remove `readOnly` values, which are generated by the server.
+    Request,
+    /// Fail with [`MaskError::ReadOnly`] when there's a `readOnly` value.
+    StrictRequest,
+    /// Remove `writeOnly` values (e.g. secrets), which must never be returned.
+    Response,
+}
 
-    #[test]
-    pub fn mask_json_simple_schema_partially_filtered() {
-        let mut json = get_mixed_json();
+/// How [`JsonMasker`] treats a scalar value that isn't one of the values allowed by the `enum` or
+/// `const` of its schema.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum EnumMismatch {
+    /// Keep the value as is.
+    #[default]
+    Keep,
+    /// Remove the value.
+    Drop,
+    /// Replace the value with a fallback.
+    Replace(Value),
+    /// Fail with [`MaskError::EnumMismatch`].
+    Error,
+}
 
-        get_masker(SIMPLE_SCHEMA).mask(&mut json);
+/// A JSON Schema draft.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Draft {
+    /// JSON Schema draft 04.
+    Draft4,
+    /// JSON Schema draft 06.
+    Draft6,
+    /// JSON Schema draft 07.
+    Draft7,
+    /// JSON Schema 2019-09.
+    Draft201909,
+    /// JSON Schema 2020-12.
+    Draft202012,
+}
 
-        assert_eq!(NONCE, json["nonce"].as_u64().unwrap());
-        assert!(json.get("foo").is_none());
+impl Draft {
+    // The draft of a $schema URI, with or without the trailing empty fragment.
+    fn from_uri(uri: &str) -> Option<Self> {
+        match uri.trim_end_matches('#') {
+            "http://json-schema.org/draft-04/schema" => Some(Draft::Draft4),
+            "http://json-schema.org/draft-06/schema" => Some(Draft::Draft6),
+            "http://json-schema.org/draft-07/schema" => Some(Draft::Draft7),
+            "https://json-schema.org/draft/2019-09/schema" => Some(Draft::Draft201909),
+            "https://json-schema.org/draft/2020-12/schema" => Some(Draft::Draft202012),
+            _ => None,
+        }
     }
 
-    #[test]
-    pub fn mask_json_nested_schema_exact_match() {
-        let mut json = get_metadata_json();
+    // Whether the keywords next to $ref apply, which they do since draft 2019-09.
+    fn applies_ref_siblings(self) -> bool {
+        matches!(self, Draft::Draft201909 | Draft::Draft202012)
+    }
 
-        let timestamp = json!({
-            "createdOn": CREATED_ON,
-            "expiresOn": EXPIRES_ON
-        });
+    // The keywords of the draft, and of the drafts before it.
+    fn keywords(self) -> Vec<&'static str> {
+        let mut keywords = DRAFT4_KEYWORDS.to_vec();
 
-        json["timestamp"] = timestamp;
+        if self != Draft::Draft4 {
+            keywords.extend(DRAFT6_KEYWORDS);
+        }
+        if matches!(
+            self,
+            Draft::Draft7 | Draft::Draft201909 | Draft::Draft202012
+        ) {
+            keywords.extend(DRAFT7_KEYWORDS);
+        }
+        if matches!(self, Draft::Draft201909 | Draft::Draft202012) {
+            keywords.extend(DRAFT201909_KEYWORDS);
+        }
+        if self == Draft::Draft202012 {
+            keywords.extend(DRAFT202012_KEYWORDS);
+        }
 
-        get_masker(NESTED_SCHEMA).mask(&mut json);
+        keywords
+    }
+}
 
-        assert_eq!(NONCE, json["nonce"].as_u64().unwrap());
-        assert_eq!(
-            VM_ID,
-            Uuid::from_str(json["vmId"].as_str().unwrap()).unwrap()
-        );
-        assert_eq!(CREATED_ON, json["timestamp"]["createdOn"].as_str().unwrap());
-        assert_eq!(EXPIRES_ON, json["timestamp"]["expiresOn"].as_str().unwrap());
+impl From<Draft> for jsonschema::Draft {
+    fn from(draft: Draft) -> Self {
+        match draft {
+            Draft::Draft4 => jsonschema::Draft::Draft4,
+            Draft::Draft6 => jsonschema::Draft::Draft6,
+            Draft::Draft7 => jsonschema::Draft::Draft7,
+            Draft::Draft201909 => jsonschema::Draft::Draft201909,
+            Draft::Draft202012 => jsonschema::Draft::Draft202012,
+        }
     }
+}
 
-    #[test]
-    pub fn mask_json_nested_schema_all_filtered() {
-        let mut json = get_foobar_json();
+/// How keywords that aren't part of the draft of a schema are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownKeywords {
+    /// Ignore unknown keywords, like JSON Schema does.
+    #[default]
+    Lenient,
+    /// Reject a schema with an unknown keyword. Extension keywords prefixed with `x-`, and the
+    /// OpenAPI `discriminator`, are still allowed.
+    Strict,
+}
 
-        let nested_object = json!({
-            "foo": FOO,
-            "bar": BAR
-        });
+/// How [`JsonMasker`] treats a value that isn't of the type declared by its schema, e.g. a string
+/// where the schema expects an object.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TypeMismatch {
+    /// Keep the value as is.
+    #[default]
+    Keep,
+    /// Remove the value.
+    Drop,
+    /// Fail with [`MaskError::TypeMismatch`].
+    Error,
+}
 
-        json["foobar"] = nested_object;
+pub struct ValidJsonSchema {
+    schema: Value,
+    validator: Arc<JSONSchema>,
+}
+
+#[derive(Error, Debug)]
+pub enum ParseError {
+    #[error("serde json could not parse the invalid json")]
+    InvalidJson(#[from] Error),
+    #[error("the provided json was valid, but it wasn't a valid json schema: {0}")]
+    InvalidJsonSchema(String),
+    #[error("the json schema reference {0} could not be resolved")]
+    InvalidReference(String),
+    #[error("the property '{name}' in '{pointer}' is the source of more than one property")]
+    RenameConflict { pointer: String, name: String },
+}
 
-        get_masker(NESTED_SCHEMA).mask(&mut json);
+/// Why a masked document doesn't conform to the schema, see [`JsonMasker::with_validate_output`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputError {
+    /// The JSON pointer of the value in the document.
+    pub pointer: String,
+    /// The keyword of the schema the value doesn't conform to.
+    pub keyword: String,
+    /// A description of the error.
+    pub message: String,
+}
 
-        assert!(json.get("foo").is_none());
-        assert!(json.get("bar").is_none());
-        assert!(json.get("foobar").is_none());
+#[derive(Error, Debug)]
+pub enum MaskError {
+    #[error("the value at '{pointer}' is {found}, but the schema expects {expected}")]
+    TypeMismatch {
+        pointer: String,
+        expected: String,
+        found: String,
+    },
+    #[error("the value at '{pointer}' is read only, so it can't be in a request")]
+    ReadOnly { pointer: String },
+    #[error("the value at '{pointer}' is {value}, which isn't allowed by the schema")]
+    EnumMismatch { pointer: String, value: Value },
+    #[error(
+        "the discriminator '{property}' at '{pointer}' is {value}, which doesn't map to a schema"
+    )]
+    UnknownDiscriminator {
+        pointer: String,
+        property: String,
+        value: Value,
+    },
+    #[error("the subschema at '{pointer}' could not be compiled: {message}")]
+    InvalidSubschema { pointer: String, message: String },
+    #[error("the masked document doesn't conform to the schema, {} error(s)", .0.len())]
+    InvalidOutput(Vec<OutputError>),
+}
+
+impl ValidJsonSchema {
+    pub fn new(schema: Value) -> Result<Self, ParseError> {
+        Self::with_options(schema, &MaskOptions::default())
     }
 
-    #[test]
-    pub fn mask_json_nested_schema_partially_filtered() {
-        let mut json = get_mixed_json();
-        let nested_object = json!({
-            "createdOn": CREATED_ON,
-            "bar": BAR
-        });
+    pub fn with_options(schema: Value, options: &MaskOptions) -> Result<Self, ParseError> {
+        // JSONSchema will validate that the nested portion of a schema is valid, but if the root
+        // isn't then it will accept it anyway. This violates our invariants, so we need to check
+        // them explicitly at the root. The root either has a type (or type array), or the type is
+        // inferred from its keywords, or it's composed from other schemas.
+        let valid_root = schema
+            .as_object()
+            .is_some_and(|root| match root.get("type") {
+                Some(Value::String(_)) => true,
+                Some(Value::Array(types)) => types.iter().all(Value::is_string),
+                Some(_) => false,
+                None => OBJECT_KEYWORDS
+                    .iter()
+                    .chain(ARRAY_KEYWORDS)
+                    .chain(COMPOSITION_KEYWORDS)
+                    .any(|keyword| root.contains_key(*keyword)),
+            });
 
-        json["timestamp"] = nested_object;
+        if !valid_root {
+            return Err(ParseError::InvalidJsonSchema(
+                "Invalid JSON Schema object".to_string(),
+            ));
+        }
 
-        get_masker(NESTED_SCHEMA).mask(&mut json);
+        let declared = schema.get("$schema").and_then(Value::as_str);
 
-        assert_eq!(NONCE, json["nonce"].as_u64().unwrap());
-        assert!(json.get("foo").is_none());
-        assert!(json.get("timestamp").unwrap().is_object());
-        assert_eq!(CREATED_ON, json["timestamp"]["createdOn"].as_str().unwrap());
-        assert!(json["timestamp"].get("bar").is_none());
-    }
+        match (declared, options.draft) {
+            (None, _) if options.require_schema_keyword => {
+                return Err(ParseError::InvalidJsonSchema(
+                    "the schema doesn't declare its draft with $schema".to_string(),
+                ));
+            }
+            (Some(uri), Some(draft)) if Draft::from_uri(uri) != Some(draft) => {
+                return Err(ParseError::InvalidJsonSchema(format!(
+                    "the schema declares {uri}, but {draft:?} is required"
+                )));
+            }
+            _ => {}
+        }
 
-    const SIMPLE_SCHEMA: &str = r#"
-{
-    "$schema": "http://json-schema.org/draft-04/schema",
-    "title": "Simple Schema",
-    "description": "Arbitrary object for testing",
-    "type": "object",
-    "properties": {
-        "nonce": {
-            "type": "string"
-        },
-        "vmId": {
-            "type": "string"
-        },
-        "foo2": {
-            "type": "string"
+        if options.unknown_keywords == UnknownKeywords::Strict {
+            check_keywords(&schema, &options.draft_of(&schema).keywords(), "")?;
         }
+
+        let validator = options
+            .compilation_options()
+            .compile(&schema)
+            .map_err(|error| ParseError::InvalidJsonSchema(error.to_string()))?;
+
+        Ok(ValidJsonSchema {
+            schema,
+            validator: Arc::new(validator),
+        })
     }
 }
-"#;
 
-    const NESTED_SCHEMA: &str = r#"
+pub fn from_str(json: &str) -> Result<Mask, ParseError> {
+    from_str_with_options(json, &MaskOptions::default())
+}
+
+pub fn from_str_with_options(json: &str, options: &MaskOptions) -> Result<Mask, ParseError> {
+    let schema = ValidJsonSchema::with_options(serde_json::from_str::<Value>(json)?, options)?;
+    Mask::from_schema(&schema, options)
+}
+
+pub fn from_reader<R>(reader: R) -> Result<Mask, ParseError>
+where
+    R: std::io::Read,
 {
-    "$schema": "http://json-schema.org/draft-04/schema",
-    "title": "Simple Schema",
-    "description": "Arbitrary nested object for testing",
-    "type": "object",
-    "properties": {
-        "nonce": {
-            "type": "string"
-        },
-        "vmId": {
-            "type": "string"
+    from_reader_with_options(reader, &MaskOptions::default())
+}
+
+pub fn from_reader_with_options<R>(reader: R, options: &MaskOptions) -> Result<Mask, ParseError>
+where
+    R: std::io::Read,
+{
+    let schema =
+        ValidJsonSchema::with_options(serde_json::from_reader::<R, Value>(reader)?, options)?;
+    Mask::from_schema(&schema, options)
+}
+
+// The URI the schema is registered under when compiling the validators of subschemas, unless the
+// schema declares its own id.
+const DEFAULT_BASE_URI: &str = "json-mask:///schema";
+
+struct SchemaParser<'a> {
+    root: &'a Value,
+    options: &'a MaskOptions,
+    nodes: Vec<MaskNode>,
+    // The node built for each JSON pointer. A node is registered before its children are parsed,
+    // so a reference back to a schema that's still being built reuses its node.
+    pointers: HashMap<String, NodeId>,
+    // The references currently being followed, used to detect a chain of references that never
+    // reaches a schema (e.g. a $ref to itself).
+    resolving: Vec<String>,
+    draft: Draft,
+    // The node shared by every false schema.
+    drop_node: Option<NodeId>,
+    // The node shared by every reference to another document.
+    keep_node: Option<NodeId>,
+}
+
+impl<'a> SchemaParser<'a> {
+    fn new(root: &'a Value, options: &'a MaskOptions) -> Self {
+        SchemaParser {
+            root,
+            options,
+            nodes: Vec::new(),
+            pointers: HashMap::new(),
+            resolving: Vec::new(),
+            draft: options.draft_of(root),
+            drop_node: None,
+            keep_node: None,
+        }
+    }
+
+    fn parse(mut self, validator: Arc<JSONSchema>) -> Result<Mask, ParseError> {
+        let root = self.parse_schema_node(self.root, String::new())?;
+        self.check_renames()?;
+        Ok(Mask {
+            nodes: self.nodes,
+            root,
+            validator,
+            compiler: SubschemaCompiler::new(self.root, self.options),
+        })
+    }
+
+    fn parse_schema_node(
+        &mut self,
+        node: &'a Value,
+        pointer: String,
+    ) -> Result<NodeId, ParseError> {
+        if node == &Value::Bool(false) {
+            return Ok(self.drop_node());
+        }
+
+        let schema = node.as_object();
+
+        // Before draft 2019-09 the keywords next to $ref are ignored, since then they apply
+        // alongside the referenced schema, which becomes one more allOf branch of the node.
+        if let Some(reference) = schema
+            .and_then(|schema| schema.get("$ref"))
+            .and_then(Value::as_str)
+        {
+            if !(self.draft.applies_ref_siblings() && schema.is_some_and(|schema| schema.len() > 1))
+            {
+                return self.parse_reference(reference);
+            }
+        }
+
+        let id = self.nodes.len();
+        self.nodes.push(MaskNode::default());
+        self.pointers.insert(pointer.clone(), id);
+
+        // Anything else that isn't an object schema (i.e. the true schema) is a leaf, meaning the
+        // value is kept as is.
+        let mut mask_node = MaskNode::default();
+
+        if let Some(schema) = schema {
+            // The keywords are parsed regardless of the type, because a subschema without a type
+            // (e.g. an allOf branch) still describes the properties of the value it applies to.
+            mask_node.types = parse_types(schema);
+            mask_node.object = has_type(schema, "object", OBJECT_KEYWORDS);
+            mask_node.array = has_type(schema, "array", ARRAY_KEYWORDS);
+            mask_node.read_only = schema.get("readOnly").is_some_and(|t| t == true);
+            mask_node.write_only = schema.get("writeOnly").is_some_and(|t| t == true);
+            mask_node.deprecated = self.is_deprecated(node);
+            mask_node.allowed_values = match (schema.get("enum"), schema.get("const")) {
+                (Some(Value::Array(values)), _) => Some(values.clone()),
+                (_, Some(value)) => Some(vec![value.clone()]),
+                _ => None,
+            };
+            mask_node.default = schema.get("default").cloned();
+            mask_node.enum_fallback = schema.get("x-enum-fallback").cloned();
+            if let Some(enum_map) = schema.get("x-enum-map") {
+                let enum_map = enum_map.as_object().ok_or_else(|| {
+                    ParseError::InvalidJsonSchema(format!(
+                        "x-enum-map at '{pointer}' must be an object"
+                    ))
+                })?;
+                mask_node.enum_map = enum_map.clone().into_iter().collect();
+            }
+            check_enum_replacements(&mask_node, &pointer)?;
+
+            self.parse_object(&mut mask_node, schema, &pointer)?;
+            self.parse_array(&mut mask_node, schema, &pointer)?;
+            self.parse_composition(&mut mask_node, schema, &pointer)?;
+        }
+
+        mask_node.pointer = pointer;
+        self.nodes[id] = mask_node;
+
+        Ok(id)
+    }
+
+    // Each name can only be the source of one property among the nodes that apply to the same
+    // object, otherwise it's ambiguous which property its value belongs to. This runs once every
+    // node is built, since the nodes that apply together can form a cycle.
+    fn check_renames(&self) -> Result<(), ParseError> {
+        for id in 0..self.nodes.len() {
+            let applicable = self.applicable_together(id);
+            let mut claimed: HashMap<&str, &str> = HashMap::new();
+
+            for mask_node in applicable.iter().map(|id| &self.nodes[*id]) {
+                for (name, sources) in &mask_node.renames {
+                    for source in sources {
+                        let is_property = applicable
+                            .iter()
+                            .any(|id| self.nodes[*id].properties.contains_key(source));
+
+                        if is_property
+                            || claimed
+                                .insert(source, name)
+                                .is_some_and(|claim| claim != name)
+                        {
+                            return Err(ParseError::RenameConflict {
+                                pointer: format!("{}/properties", mask_node.pointer),
+                                name: source.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // The node and every subschema that may apply to the same value alongside it.
+    fn applicable_together(&self, id: NodeId) -> Vec<NodeId> {
+        let mut applicable = vec![id];
+        let mut next = 0;
+
+        while let Some(&id) = applicable.get(next) {
+            let mask_node = &self.nodes[id];
+            let conditional = mask_node.conditional.iter().flat_map(|conditional| {
+                [
+                    Some(conditional.condition.node),
+                    conditional.then,
+                    conditional.otherwise,
+                ]
+                .into_iter()
+                .flatten()
+            });
+
+            for id in mask_node
+                .all_of
+                .iter()
+                .copied()
+                .chain(mask_node.any_of.iter().map(|branch| branch.node))
+                .chain(mask_node.one_of.iter().map(|branch| branch.node))
+                .chain(conditional)
+                .chain(mask_node.dependent_schemas.iter().map(|(_, id)| *id))
+                .chain(
+                    mask_node
+                        .discriminator
+                        .iter()
+                        .flat_map(|discriminator| discriminator.mapping.values().copied()),
+                )
+                .collect::<Vec<_>>()
+            {
+                if !applicable.contains(&id) {
+                    applicable.push(id);
+                }
+            }
+
+            next += 1;
+        }
+
+        applicable
+    }
+
+    fn drop_node(&mut self) -> NodeId {
+        *self.drop_node.get_or_insert_with(|| {
+            self.nodes.push(MaskNode {
+                drop: true,
+                ..MaskNode::default()
+            });
+            self.nodes.len() - 1
+        })
+    }
+
+    fn keep_node(&mut self) -> NodeId {
+        *self.keep_node.get_or_insert_with(|| {
+            self.nodes.push(MaskNode::default());
+            self.nodes.len() - 1
+        })
+    }
+
+    fn parse_object(
+        &mut self,
+        mask_node: &mut MaskNode,
+        schema: &'a Map<String, Value>,
+        pointer: &str,
+    ) -> Result<(), ParseError> {
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (key, child) in properties {
+                let child_pointer = format!("{pointer}/properties/{}", escape_pointer(key));
+                let child_node = self.parse_schema_node(child, child_pointer)?;
+
+                if !(self.options.exclude_deprecated && self.is_deprecated(child)) {
+                    mask_node.properties.insert(key.clone(), child_node);
+
+                    let sources = parse_source_names(key, child)?;
+                    if !sources.is_empty() {
+                        mask_node.renames.push((key.clone(), sources));
+                    }
+
+                    if let Some(source) = child.get("x-source") {
+                        match source.as_str() {
+                            Some(source) if source.is_empty() || source.starts_with('/') => {
+                                mask_node.moves.push((key.clone(), source.to_string()))
+                            }
+                            _ => {
+                                return Err(ParseError::InvalidJsonSchema(format!(
+                                    "x-source of the property '{key}' must be a JSON pointer"
+                                )))
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(required) = schema.get("required").and_then(Value::as_array) {
+            mask_node.required = required
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect();
+        }
+
+        if let Some(pattern_properties) = schema.get("patternProperties").and_then(Value::as_object)
+        {
+            for (pattern, child) in pattern_properties {
+                let regex = Regex::new(pattern).map_err(|error| {
+                    ParseError::InvalidJsonSchema(format!("invalid pattern {pattern}: {error}"))
+                })?;
+                let child_pointer =
+                    format!("{pointer}/patternProperties/{}", escape_pointer(pattern));
+                let child = self.parse_schema_node(child, child_pointer)?;
+                mask_node.pattern_properties.push((regex, child));
+            }
+        }
+
+        mask_node.additional_properties =
+            self.parse_additional(schema, pointer, "additionalProperties")?;
+        mask_node.unevaluated_properties =
+            self.parse_additional(schema, pointer, "unevaluatedProperties")?;
+
+        Ok(())
+    }
+
+    // Whether the schema, or the schema it references, is deprecated. This is the one definition
+    // used both to exclude deprecated properties and to report deprecated values. The schema is
+    // checked rather than its node, because the node of a recursive schema may still be being
+    // built.
+    fn is_deprecated(&self, mut schema: &'a Value) -> bool {
+        let mut followed = Vec::new();
+
+        loop {
+            let reference = schema.get("$ref").and_then(Value::as_str);
+
+            // Before draft 2019-09 the keywords next to $ref are ignored.
+            if (reference.is_none() || self.draft.applies_ref_siblings())
+                && schema.get("deprecated").is_some_and(|t| t == true)
+            {
+                return true;
+            }
+
+            match reference
+                .and_then(|reference| reference.strip_prefix('#'))
+                .filter(|pointer| !followed.contains(pointer))
+                .and_then(|pointer| Some((pointer, self.root.pointer(pointer)?)))
+            {
+                Some((pointer, target)) => {
+                    followed.push(pointer);
+                    schema = target;
+                }
+                None => return false,
+            }
+        }
+    }
+
+    fn parse_additional(
+        &mut self,
+        schema: &'a Map<String, Value>,
+        pointer: &str,
+        keyword: &str,
+    ) -> Result<Option<Additional>, ParseError> {
+        Ok(match schema.get(keyword) {
+            None => None,
+            Some(Value::Bool(true)) => Some(Additional::Keep),
+            Some(child) if child.is_object() => Some(Additional::Mask(
+                self.parse_schema_node(child, format!("{pointer}/{keyword}"))?,
+            )),
+            Some(_) => Some(Additional::Drop),
+        })
+    }
+
+    fn parse_array(
+        &mut self,
+        mask_node: &mut MaskNode,
+        schema: &'a Map<String, Value>,
+        pointer: &str,
+    ) -> Result<(), ParseError> {
+        // Draft 2020-12 describes tuples with prefixItems and the rest with items, older drafts
+        // use the array form of items and describe the rest with additionalItems.
+        let (prefix_items, items) = match (schema.get("prefixItems"), schema.get("items")) {
+            (Some(Value::Array(prefix_items)), _) => (Some(("prefixItems", prefix_items)), "items"),
+            (_, Some(Value::Array(prefix_items))) => {
+                (Some(("items", prefix_items)), "additionalItems")
+            }
+            _ => (None, "items"),
+        };
+
+        if let Some((keyword, prefix_items)) = prefix_items {
+            for (index, prefix_item) in prefix_items.iter().enumerate() {
+                let prefix_item =
+                    self.parse_schema_node(prefix_item, format!("{pointer}/{keyword}/{index}"))?;
+                mask_node.prefix_items.push(prefix_item);
+            }
+        }
+
+        if let Some(child) = schema.get(items) {
+            mask_node.items = Some(self.parse_schema_node(child, format!("{pointer}/{items}"))?);
+        }
+
+        Ok(())
+    }
+
+    fn parse_composition(
+        &mut self,
+        mask_node: &mut MaskNode,
+        schema: &'a Map<String, Value>,
+        pointer: &str,
+    ) -> Result<(), ParseError> {
+        mask_node.all_of = self
+            .parse_branches(schema, pointer, "allOf")?
+            .into_iter()
+            .map(|(branch, _)| branch)
+            .collect();
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            mask_node.all_of.push(self.parse_reference(reference)?);
+        }
+
+        mask_node.any_of = self.parse_validated_branches(schema, pointer, "anyOf")?;
+        mask_node.one_of = self.parse_validated_branches(schema, pointer, "oneOf")?;
+        mask_node.conditional = self.parse_conditional(schema, pointer)?;
+        mask_node.discriminator = self.parse_discriminator(schema)?;
+
+        // Draft 2019-09 split dependencies into dependentRequired and dependentSchemas, only the
+        // schema form says anything about which properties to keep.
+        for keyword in ["dependencies", "dependentSchemas"] {
+            if let Some(dependencies) = schema.get(keyword).and_then(Value::as_object) {
+                for (key, dependency) in dependencies {
+                    if dependency.is_object() || dependency.is_boolean() {
+                        let dependency_pointer =
+                            format!("{pointer}/{keyword}/{}", escape_pointer(key));
+                        let dependency = self.parse_schema_node(dependency, dependency_pointer)?;
+                        mask_node.dependent_schemas.push((key.clone(), dependency));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_discriminator(
+        &mut self,
+        schema: &'a Map<String, Value>,
+    ) -> Result<Option<Discriminator>, ParseError> {
+        let Some(discriminator) = schema.get("discriminator").and_then(Value::as_object) else {
+            return Ok(None);
+        };
+
+        let Some(property) = discriminator.get("propertyName").and_then(Value::as_str) else {
+            return Ok(None);
+        };
+
+        let mut mapping = HashMap::new();
+
+        // Without an explicit mapping, the value is the name of a referenced branch.
+        for keyword in ["anyOf", "oneOf"] {
+            for branch in schema
+                .get(keyword)
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                if let Some(reference) = branch.get("$ref").and_then(Value::as_str) {
+                    let name = reference.rsplit('/').next().unwrap_or(reference);
+                    mapping.insert(name.to_string(), self.parse_reference(reference)?);
+                }
+            }
+        }
+
+        for (value, reference) in discriminator
+            .get("mapping")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+        {
+            let Some(reference) = reference.as_str() else {
+                continue;
+            };
+
+            // A mapping can also be the bare name of a schema in the components of the document.
+            let node = if reference.contains('/') {
+                self.parse_reference(reference)?
+            } else {
+                self.parse_reference(&format!("#/components/schemas/{reference}"))?
+            };
+
+            mapping.insert(value.clone(), node);
+        }
+
+        Ok(Some(Discriminator {
+            property: property.to_string(),
+            mapping,
+        }))
+    }
+
+    fn parse_conditional(
+        &mut self,
+        schema: &'a Map<String, Value>,
+        pointer: &str,
+    ) -> Result<Option<Conditional>, ParseError> {
+        let Some(condition) = schema.get("if") else {
+            return Ok(None);
+        };
+
+        let condition_pointer = format!("{pointer}/if");
+        let condition = Branch::new(
+            self.parse_schema_node(condition, condition_pointer.clone())?,
+            condition_pointer,
+        );
+
+        let mut parse_keyword = |keyword| match schema.get(keyword) {
+            Some(child) => self
+                .parse_schema_node(child, format!("{pointer}/{keyword}"))
+                .map(Some),
+            None => Ok(None),
+        };
+
+        Ok(Some(Conditional {
+            condition,
+            then: parse_keyword("then")?,
+            otherwise: parse_keyword("else")?,
+        }))
+    }
+
+    fn parse_validated_branches(
+        &mut self,
+        schema: &'a Map<String, Value>,
+        pointer: &str,
+        keyword: &str,
+    ) -> Result<Vec<Branch>, ParseError> {
+        Ok(self
+            .parse_branches(schema, pointer, keyword)?
+            .into_iter()
+            .map(|(node, pointer)| Branch::new(node, pointer))
+            .collect())
+    }
+
+    fn parse_branches(
+        &mut self,
+        schema: &'a Map<String, Value>,
+        pointer: &str,
+        keyword: &str,
+    ) -> Result<Vec<(NodeId, String)>, ParseError> {
+        let mut branches = Vec::new();
+
+        if let Some(subschemas) = schema.get(keyword).and_then(Value::as_array) {
+            for (index, subschema) in subschemas.iter().enumerate() {
+                let branch_pointer = format!("{pointer}/{keyword}/{index}");
+                let branch = self.parse_schema_node(subschema, branch_pointer.clone())?;
+                branches.push((branch, branch_pointer));
+            }
+        }
+
+        Ok(branches)
+    }
+
+    fn parse_reference(&mut self, reference: &str) -> Result<NodeId, ParseError> {
+        // A reference to another document can't be followed, so the value is kept as is, the same
+        // way as for the true schema.
+        let (uri, fragment) = reference.split_once('#').unwrap_or((reference, ""));
+        if !uri.is_empty() && Some(uri) != root_uri(self.root) {
+            return Ok(self.keep_node());
+        }
+
+        // The fragment is either a percent-encoded JSON pointer (e.g. #/$defs/a%20b) or the name
+        // of an anchor (e.g. #foo).
+        let pointer = if fragment.is_empty() || fragment.starts_with('/') {
+            decode_fragment(fragment)
+        } else {
+            find_anchor(self.root, fragment, String::new())
+        };
+        let Some((pointer, target)) = pointer.and_then(|pointer| {
+            let target = self.root.pointer(&pointer)?;
+            Some((pointer, target))
+        }) else {
+            return Err(ParseError::InvalidReference(reference.to_string()));
+        };
+        let pointer = pointer.as_str();
+
+        if let Some(id) = self.pointers.get(pointer) {
+            return Ok(*id);
+        }
+
+        if self.resolving.iter().any(|resolving| resolving == pointer) {
+            return Err(ParseError::InvalidJsonSchema(format!(
+                "reference {reference} never resolves to a schema"
+            )));
+        }
+
+        self.resolving.push(pointer.to_string());
+        let id = self.parse_schema_node(target, pointer.to_string());
+        self.resolving.pop();
+
+        // The target may itself be a reference, in which case it wasn't registered yet.
+        let id = id?;
+        self.pointers.insert(pointer.to_string(), id);
+
+        Ok(id)
+    }
+}
+
+// The keywords that only apply to objects (or arrays), used to infer the type of a schema that
+// doesn't declare one.
+const OBJECT_KEYWORDS: &[&str] = &[
+    "properties",
+    "patternProperties",
+    "additionalProperties",
+    "unevaluatedProperties",
+    "required",
+    "minProperties",
+    "maxProperties",
+    "propertyNames",
+    "dependencies",
+    "dependentSchemas",
+    "dependentRequired",
+];
+const ARRAY_KEYWORDS: &[&str] = &[
+    "items",
+    "prefixItems",
+    "additionalItems",
+    "unevaluatedItems",
+    "contains",
+    "minItems",
+    "maxItems",
+    "uniqueItems",
+];
+
+// The keywords that compose a schema from other schemas, which may declare the type.
+const COMPOSITION_KEYWORDS: &[&str] = &["$ref", "allOf", "anyOf", "oneOf"];
+
+const DRAFT4_KEYWORDS: &[&str] = &[
+    "id",
+    "$schema",
+    "$ref",
+    "title",
+    "description",
+    "default",
+    "type",
+    "enum",
+    "format",
+    "multipleOf",
+    "maximum",
+    "exclusiveMaximum",
+    "minimum",
+    "exclusiveMinimum",
+    "maxLength",
+    "minLength",
+    "pattern",
+    "items",
+    "additionalItems",
+    "maxItems",
+    "minItems",
+    "uniqueItems",
+    "maxProperties",
+    "minProperties",
+    "required",
+    "properties",
+    "patternProperties",
+    "additionalProperties",
+    "dependencies",
+    "allOf",
+    "anyOf",
+    "oneOf",
+    "not",
+    "definitions",
+];
+const DRAFT6_KEYWORDS: &[&str] = &["$id", "const", "contains", "propertyNames", "examples"];
+const DRAFT7_KEYWORDS: &[&str] = &[
+    "$comment",
+    "if",
+    "then",
+    "else",
+    "readOnly",
+    "writeOnly",
+    "contentMediaType",
+    "contentEncoding",
+];
+const DRAFT201909_KEYWORDS: &[&str] = &[
+    "$anchor",
+    "$defs",
+    "$recursiveRef",
+    "$recursiveAnchor",
+    "$vocabulary",
+    "dependentSchemas",
+    "dependentRequired",
+    "unevaluatedProperties",
+    "unevaluatedItems",
+    "maxContains",
+    "minContains",
+    "contentSchema",
+    "deprecated",
+];
+const DRAFT202012_KEYWORDS: &[&str] = &["prefixItems", "$dynamicRef", "$dynamicAnchor"];
+
+// The keywords whose value is a map of subschemas, a subschema, and an array of subschemas.
+const SCHEMA_MAP_KEYWORDS: &[&str] = &[
+    "properties",
+    "patternProperties",
+    "definitions",
+    "$defs",
+    "dependencies",
+    "dependentSchemas",
+];
+const SCHEMA_KEYWORDS: &[&str] = &[
+    "items",
+    "additionalItems",
+    "unevaluatedItems",
+    "contains",
+    "additionalProperties",
+    "unevaluatedProperties",
+    "propertyNames",
+    "not",
+    "if",
+    "then",
+    "else",
+    "contentSchema",
+];
+const SCHEMA_ARRAY_KEYWORDS: &[&str] = &["items", "prefixItems", "allOf", "anyOf", "oneOf"];
+
+// The keywords whose value is data rather than a subschema.
+const NON_SCHEMA_KEYWORDS: &[&str] = &["enum", "const", "default", "examples"];
+
+// Checks that the schema, and its subschemas, only use the keywords.
+fn check_keywords(schema: &Value, keywords: &[&str], pointer: &str) -> Result<(), ParseError> {
+    let Some(schema) = schema.as_object() else {
+        return Ok(());
+    };
+
+    for (keyword, value) in schema {
+        if !keywords.contains(&keyword.as_str())
+            && !keyword.starts_with("x-")
+            && keyword != "discriminator"
+        {
+            return Err(ParseError::InvalidJsonSchema(format!(
+                "unknown keyword '{keyword}' at '{pointer}'"
+            )));
+        }
+
+        let keyword_pointer = format!("{pointer}/{}", escape_pointer(keyword));
+
+        match value {
+            Value::Object(children) if SCHEMA_MAP_KEYWORDS.contains(&keyword.as_str()) => {
+                for (key, child) in children {
+                    check_keywords(
+                        child,
+                        keywords,
+                        &format!("{keyword_pointer}/{}", escape_pointer(key)),
+                    )?;
+                }
+            }
+            Value::Object(_) if SCHEMA_KEYWORDS.contains(&keyword.as_str()) => {
+                check_keywords(value, keywords, &keyword_pointer)?;
+            }
+            Value::Array(children) if SCHEMA_ARRAY_KEYWORDS.contains(&keyword.as_str()) => {
+                for (index, child) in children.iter().enumerate() {
+                    check_keywords(child, keywords, &format!("{keyword_pointer}/{index}"))?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+// Checks that the replacements of values that aren't allowed are themselves allowed, otherwise a
+// typo would present a value to down-level clients that they don't know either. Replacements
+// without an enum (or const) would never apply, so they're rejected too.
+fn check_enum_replacements(mask_node: &MaskNode, pointer: &str) -> Result<(), ParseError> {
+    let Some(allowed_values) = &mask_node.allowed_values else {
+        if mask_node.enum_map.is_empty() && mask_node.enum_fallback.is_none() {
+            return Ok(());
+        }
+
+        return Err(ParseError::InvalidJsonSchema(format!(
+            "{pointer} has x-enum-map or x-enum-fallback, but no enum or const"
+        )));
+    };
+
+    let replacements = mask_node
+        .enum_map
+        .iter()
+        .map(|(value, replacement)| (format!("x-enum-map/{}", escape_pointer(value)), replacement))
+        .chain(
+            mask_node
+                .enum_fallback
+                .iter()
+                .map(|fallback| ("x-enum-fallback".to_string(), fallback)),
+        );
+
+    for (keyword, replacement) in replacements {
+        if !allowed_values
+            .iter()
+            .any(|allowed_value| json_equal(allowed_value, replacement))
+        {
+            return Err(ParseError::InvalidJsonSchema(format!(
+                "{pointer}/{keyword} is {replacement}, which isn't allowed by the enum"
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+// The names a property is read from when it's missing, x-source-name followed by x-aliases.
+fn parse_source_names(key: &str, schema: &Value) -> Result<Vec<String>, ParseError> {
+    let invalid = |keyword: &str| {
+        ParseError::InvalidJsonSchema(format!(
+            "{keyword} of the property '{key}' must be a string (or an array of strings)"
+        ))
+    };
+
+    let mut sources = Vec::new();
+
+    if let Some(source_name) = schema.get("x-source-name") {
+        let source_name = source_name
+            .as_str()
+            .ok_or_else(|| invalid("x-source-name"))?;
+        sources.push(source_name.to_string());
+    }
+
+    if let Some(aliases) = schema.get("x-aliases") {
+        for alias in aliases.as_array().ok_or_else(|| invalid("x-aliases"))? {
+            sources.push(
+                alias
+                    .as_str()
+                    .ok_or_else(|| invalid("x-aliases"))?
+                    .to_string(),
+            );
+        }
+    }
+
+    let mut unique = Vec::new();
+    for source in sources {
+        if source != key && !unique.contains(&source) {
+            unique.push(source);
+        }
+    }
+
+    Ok(unique)
+}
+
+// Whether the schema allows values of the type, either because it's the type (or one of the type
+// array) or because the schema has no type but uses keywords of the type.
+fn has_type(schema: &Map<String, Value>, name: &str, keywords: &[&str]) -> bool {
+    match schema.get("type") {
+        Some(Value::String(schema_type)) => schema_type == name,
+        Some(Value::Array(types)) => types.iter().any(|schema_type| schema_type == name),
+        _ => keywords.iter().any(|keyword| schema.contains_key(*keyword)),
+    }
+}
+
+fn parse_types(schema: &Map<String, Value>) -> Vec<PrimitiveType> {
+    let types = match schema.get("type") {
+        Some(schema_type @ Value::String(_)) => std::slice::from_ref(schema_type),
+        Some(Value::Array(types)) => types.as_slice(),
+        _ => &[],
+    };
+
+    types
+        .iter()
+        .filter_map(|schema_type| PrimitiveType::try_from(schema_type.as_str()?).ok())
+        .collect()
+}
+
+// Whether the value is one of the types, where an empty list allows any type.
+fn is_type(value: &Value, types: &[PrimitiveType]) -> bool {
+    types.is_empty()
+        || types.iter().any(|schema_type| match schema_type {
+            PrimitiveType::Integer => value.as_f64().is_some_and(|number| number.fract() == 0.0),
+            PrimitiveType::Number => value.is_number(),
+            schema_type => *schema_type == PrimitiveType::from(value),
+        })
+}
+
+// Moves the value of a missing property from the first of its sources in the object. The property
+// is then masked under its new name.
+fn rename_properties(object: &mut Map<String, Value>, mask_nodes: &[&MaskNode]) {
+    for (name, sources) in mask_nodes.iter().flat_map(|mask_node| &mask_node.renames) {
+        if object.contains_key(name) {
+            continue;
+        }
+
+        if let Some(value) = sources.iter().find_map(|source| object.remove(source)) {
+            object.insert(name.clone(), value);
+        }
+    }
+}
+
+// Copies the value of a missing property from its source in the input document, the pointer is
+// from the root of the document rather than the object. The property is then masked as any other.
+fn move_properties(object: &mut Map<String, Value>, mask_nodes: &[&MaskNode], input: &Value) {
+    for (name, source) in mask_nodes.iter().flat_map(|mask_node| &mask_node.moves) {
+        if object.contains_key(name) {
+            continue;
+        }
+
+        if let Some(value) = input.pointer(source) {
+            object.insert(name.clone(), value.clone());
+        }
+    }
+}
+
+fn output_error(error: ValidationError) -> OutputError {
+    // The schema path ends with the keyword, followed by an index or property for some keywords.
+    let keyword = error
+        .schema_path
+        .iter()
+        .rev()
+        .find_map(|chunk| match chunk {
+            PathChunk::Keyword(keyword) => Some(keyword.to_string()),
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    OutputError {
+        pointer: error.instance_path.to_string(),
+        keyword,
+        message: error.to_string(),
+    }
+}
+
+fn type_mismatch(value: &Value, types: &[PrimitiveType], location: Location) -> MaskError {
+    MaskError::TypeMismatch {
+        pointer: location.pointer(),
+        expected: types
+            .iter()
+            .map(PrimitiveType::to_string)
+            .collect::<Vec<_>>()
+            .join(" or "),
+        found: PrimitiveType::from(value).to_string(),
+    }
+}
+
+// The types declared by the nodes, empty when none of them declares a type.
+fn declared_types(mask_nodes: &[&MaskNode]) -> Vec<PrimitiveType> {
+    let mut types = Vec::new();
+
+    for schema_type in mask_nodes.iter().flat_map(|mask_node| &mask_node.types) {
+        if !types.contains(schema_type) {
+            types.push(*schema_type);
+        }
+    }
+
+    types
+}
+
+// Converts a scalar value to the first of the types it can be converted to, unless it's already
+// one of them. Returns whether the value is now one of the types.
+fn coerce_value(value: &mut Value, types: &[PrimitiveType]) -> bool {
+    if value.is_object() || value.is_array() {
+        return true;
+    }
+
+    // A whole float is an integer, but it's written as an integer for an integer only schema.
+    if is_type(value, types) {
+        if value.is_f64()
+            && types.contains(&PrimitiveType::Integer)
+            && !types.contains(&PrimitiveType::Number)
+        {
+            match value.as_f64().and_then(integer_from_f64) {
+                Some(integer) => *value = integer,
+                None => return false,
+            }
+        }
+
+        return true;
+    }
+
+    match types
+        .iter()
+        .find_map(|schema_type| coerce_scalar(value, *schema_type))
+    {
+        Some(coerced) => {
+            *value = coerced;
+            true
+        }
+        None => false,
+    }
+}
+
+fn coerce_scalar(value: &Value, schema_type: PrimitiveType) -> Option<Value> {
+    match (schema_type, value) {
+        (PrimitiveType::String, Value::Number(number)) => Some(Value::String(number.to_string())),
+        (PrimitiveType::String, Value::Bool(boolean)) => Some(Value::String(boolean.to_string())),
+        (PrimitiveType::Boolean, Value::String(string)) => string.parse().ok().map(Value::Bool),
+        (PrimitiveType::Integer, Value::String(string)) => {
+            if let Ok(integer) = string.parse::<i64>() {
+                Some(integer.into())
+            } else if let Ok(integer) = string.parse::<u64>() {
+                Some(integer.into())
+            } else {
+                string.parse().ok().and_then(integer_from_f64)
+            }
+        }
+        (PrimitiveType::Number, Value::String(string)) => {
+            if let Ok(integer) = string.parse::<i64>() {
+                Some(integer.into())
+            } else if let Ok(integer) = string.parse::<u64>() {
+                Some(integer.into())
+            } else {
+                string
+                    .parse()
+                    .ok()
+                    .and_then(serde_json::Number::from_f64)
+                    .map(Value::Number)
+            }
+        }
+        _ => None,
+    }
+}
+
+// The integer of a whole float within the range of i64 or u64.
+fn integer_from_f64(number: f64) -> Option<Value> {
+    if number.fract() != 0.0 {
+        None
+    } else if number >= i64::MIN as f64 && number < i64::MAX as f64 {
+        Some((number as i64).into())
+    } else if number >= 0.0 && number < u64::MAX as f64 {
+        Some((number as u64).into())
+    } else {
+        None
+    }
+}
+
+// Whether two values are equal the way JSON Schema compares them, where numbers are compared by
+// value (e.g. 1.0 equals 1) rather than by representation.
+fn json_equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) if left.is_f64() || right.is_f64() => {
+            left.as_f64() == right.as_f64()
+        }
+        (Value::Array(left), Value::Array(right)) => {
+            left.len() == right.len()
+                && left
+                    .iter()
+                    .zip(right)
+                    .all(|(left, right)| json_equal(left, right))
+        }
+        (Value::Object(left), Value::Object(right)) => {
+            left.len() == right.len()
+                && left
+                    .iter()
+                    .all(|(key, left)| right.get(key).is_some_and(|right| json_equal(left, right)))
+        }
+        _ => left == right,
+    }
+}
+
+// Percent-encodes a JSON pointer for the fragment of a URI, the characters a fragment allows are
+// kept as is.
+fn encode_fragment(pointer: &str) -> String {
+    let mut fragment = String::with_capacity(pointer.len());
+
+    for byte in pointer.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@/?".contains(&byte) {
+            fragment.push(byte as char);
+        } else {
+            fragment.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    fragment
+}
+
+// Decodes the percent-encoded JSON pointer in the fragment of a URI, None when the result isn't
+// UTF-8.
+fn decode_fragment(fragment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(fragment.len());
+    let mut rest = fragment.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = match tail {
+            [high, low, ..] if byte == b'%' => std::str::from_utf8(&[*high, *low])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+// Finds the JSON pointer of the subschema with the anchor, declared by $anchor or, before draft
+// 2019-09, by an id that's only a fragment (e.g. "$id": "#foo").
+fn find_anchor(schema: &Value, anchor: &str, pointer: String) -> Option<String> {
+    match schema {
+        Value::Object(schema) => {
+            let declares = |keyword, prefix| {
+                schema
+                    .get(keyword)
+                    .and_then(Value::as_str)
+                    .and_then(|name| name.strip_prefix(prefix))
+                    .is_some_and(|name| name == anchor)
+            };
+
+            if declares("$anchor", "") || declares("$id", "#") || declares("id", "#") {
+                return Some(pointer);
+            }
+
+            // Values that aren't schemas may contain anything, including an object that looks like
+            // one.
+            schema
+                .iter()
+                .filter(|(key, _)| !NON_SCHEMA_KEYWORDS.contains(&key.as_str()))
+                .find_map(|(key, value)| {
+                    find_anchor(value, anchor, format!("{pointer}/{}", escape_pointer(key)))
+                })
+        }
+        Value::Array(values) => values
+            .iter()
+            .enumerate()
+            .find_map(|(index, value)| find_anchor(value, anchor, format!("{pointer}/{index}"))),
+        _ => None,
+    }
+}
+
+// The URI of the root schema from its id, without the fragment (e.g. a draft-07 id often ends in
+// an empty fragment).
+fn root_uri(root: &Value) -> Option<&str> {
+    root.get("$id")
+        .or_else(|| root.get("id"))
+        .and_then(Value::as_str)
+        .and_then(|id| id.split('#').next())
+        .filter(|id| !id.is_empty())
+}
+
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+impl TryFrom<&ValidJsonSchema> for Mask {
+    type Error = ParseError;
+
+    fn try_from(value: &ValidJsonSchema) -> Result<Self, Self::Error> {
+        Mask::from_schema(value, &MaskOptions::default())
+    }
+}
+
+pub struct JsonMasker {
+    mask: Mask,
+    extra_items: ExtraItems,
+    branch_selection: BranchSelection,
+    unmatched_branches: UnmatchedBranches,
+    unknown_discriminator: UnknownDiscriminator,
+    direction: Direction,
+    report_deprecated: bool,
+    enum_mismatch: EnumMismatch,
+    // Overrides of enum_mismatch, keyed by the JSON pointer of the schema with the enum.
+    field_enum_mismatch: HashMap<String, EnumMismatch>,
+    fill_defaults: bool,
+    coerce_types: bool,
+    type_mismatch: TypeMismatch,
+    strict_root_type: bool,
+    validate_output: bool,
+}
+
+// The nodes that apply to a value, or what to do with the value when they can't be determined.
+enum Applicable<'m> {
+    Nodes(Vec<&'m MaskNode>),
+    Keep,
+    Drop,
+}
+
+// The location of the value being masked. It's only turned into a JSON pointer when it's needed
+// (e.g. for an error), so masking doesn't allocate a pointer for every value.
+#[derive(Clone, Copy)]
+enum Location<'l> {
+    Root,
+    Key(&'l Location<'l>, &'l str),
+    Index(&'l Location<'l>, usize),
+}
+
+impl Location<'_> {
+    fn pointer(&self) -> String {
+        match self {
+            Location::Root => String::new(),
+            Location::Key(parent, key) => format!("{}/{}", parent.pointer(), escape_pointer(key)),
+            Location::Index(parent, index) => format!("{}/{index}", parent.pointer()),
+        }
+    }
+}
+
+impl JsonMasker {
+    pub fn new(mask: Mask) -> Self {
+        JsonMasker {
+            mask,
+            extra_items: ExtraItems::default(),
+            branch_selection: BranchSelection::default(),
+            unmatched_branches: UnmatchedBranches::default(),
+            unknown_discriminator: UnknownDiscriminator::default(),
+            direction: Direction::default(),
+            report_deprecated: false,
+            enum_mismatch: EnumMismatch::default(),
+            field_enum_mismatch: HashMap::new(),
+            fill_defaults: false,
+            coerce_types: false,
+            type_mismatch: TypeMismatch::default(),
+            strict_root_type: false,
+            validate_output: false,
+        }
+    }
+
+    pub fn with_extra_items(mut self, extra_items: ExtraItems) -> Self {
+        self.extra_items = extra_items;
+        self
+    }
+
+    pub fn with_branch_selection(mut self, branch_selection: BranchSelection) -> Self {
+        self.branch_selection = branch_selection;
+        self
+    }
+
+    pub fn with_unmatched_branches(mut self, unmatched_branches: UnmatchedBranches) -> Self {
+        self.unmatched_branches = unmatched_branches;
+        self
+    }
+
+    pub fn with_unknown_discriminator(
+        mut self,
+        unknown_discriminator: UnknownDiscriminator,
+    ) -> Self {
+        self.unknown_discriminator = unknown_discriminator;
+        self
+    }
+
+    pub fn with_direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// When enabled, [`JsonMasker::mask`] reports the `deprecated` values that were in the document
+    /// in [`MaskReport::deprecated`].
+    pub fn with_report_deprecated(mut self, report_deprecated: bool) -> Self {
+        self.report_deprecated = report_deprecated;
+        self
+    }
+
+    pub fn with_enum_mismatch(mut self, enum_mismatch: EnumMismatch) -> Self {
+        self.enum_mismatch = enum_mismatch;
+        self
+    }
+
+    /// Overrides [`JsonMasker::with_enum_mismatch`] for the values of one schema, identified by
+    /// its JSON pointer in the schema document (e.g. `/properties/status` or
+    /// `/definitions/Status` when the property is a `$ref`).
+    pub fn with_field_enum_mismatch(mut self, pointer: &str, enum_mismatch: EnumMismatch) -> Self {
+        self.field_enum_mismatch
+            .insert(pointer.to_string(), enum_mismatch);
+        self
+    }
+
+    /// When enabled, [`JsonMasker::mask`] inserts the properties missing from an object that have a
+    /// `default` in the schema. A missing object property without a `default` is created empty
+    /// when it's `required`, so that its own properties can be filled.
+    pub fn with_fill_defaults(mut self, fill_defaults: bool) -> Self {
+        self.fill_defaults = fill_defaults;
+        self
+    }
+
+    /// When enabled, [`JsonMasker::mask`] converts a scalar value to the type declared by the
+    /// schema, between numbers, booleans and their string form. A number is only converted to an
+    /// integer when it's whole and within the range of an integer. The values that can't be
+    /// converted are kept as is, and reported in [`MaskReport::unconverted`].
+    pub fn with_coerce_types(mut self, coerce_types: bool) -> Self {
+        self.coerce_types = coerce_types;
+        self
+    }
+
+    /// How a value that isn't of the type declared by its schema is treated. The root is only
+    /// checked when [`JsonMasker::with_strict_root_type`] is enabled, as it can't be removed.
+    pub fn with_type_mismatch(mut self, type_mismatch: TypeMismatch) -> Self {
+        self.type_mismatch = type_mismatch;
+        self
+    }
+
+    /// When enabled, [`JsonMasker::mask`] returns [`MaskError::TypeMismatch`] instead of keeping
+    /// the document as is when its root isn't the type declared by the root of the schema.
+    pub fn with_strict_root_type(mut self, strict_root_type: bool) -> Self {
+        self.strict_root_type = strict_root_type;
+        self
+    }
+
+    /// When enabled, [`JsonMasker::mask`] validates the masked document against the schema, and
+    /// returns [`MaskError::InvalidOutput`] when it doesn't conform. The document is masked either
+    /// way.
+    pub fn with_validate_output(mut self, validate_output: bool) -> Self {
+        self.validate_output = validate_output;
+        self
+    }
+
+    /// Masks the document in place. The document is masked as it's traversed, so when an error
+    /// other than [`MaskError::InvalidOutput`] is returned the content of the document is
+    /// unspecified: some values may be masked and others not, and it shouldn't be used.
+    pub fn mask(&self, document: &mut Value) -> Result<MaskReport, MaskError> {
+        let root = self.mask.node(self.mask.root);
+
+        if self.strict_root_type && !is_type(document, &root.types) {
+            return Err(type_mismatch(document, &root.types, Location::Root));
+        }
+
+        let mut report = MaskReport::default();
+
+        // Values are moved from the document as it was before it's masked, so it's only copied
+        // when the mask moves values.
+        let input = if self.mask.nodes.iter().any(|node| !node.moves.is_empty()) {
+            document.clone()
+        } else {
+            Value::Null
+        };
+
+        // The root can't be removed, so nothing is left of it instead.
+        if !self.mask_value(
+            document,
+            &[self.mask.root],
+            Location::Root,
+            &input,
+            &mut report,
+        )? {
+            *document = Value::Null;
+        }
+
+        if self.validate_output {
+            if let Err(errors) = self.mask.validator.validate(document) {
+                return Err(MaskError::InvalidOutput(errors.map(output_error).collect()));
+            }
+        }
+
+        Ok(report)
+    }
+
+    // Expands the nodes with every subschema that applies to the value, in other words the nodes
+    // whose union is the mask for the value.
+    fn applicable_nodes(
+        &self,
+        ids: &[NodeId],
+        value: &Value,
+        location: Location,
+    ) -> Result<Applicable<'_>, MaskError> {
+        let mut visited = Vec::new();
+        let mut pending = ids.to_vec();
+
+        while let Some(id) = pending.pop() {
+            if visited.contains(&id) {
+                continue;
+            }
+
+            visited.push(id);
+
+            let mask_node = self.mask.node(id);
+            pending.extend(mask_node.all_of.iter().rev());
+
+            // A discriminator picks the branch from the value of its property, instead of
+            // validating the value against every branch.
+            match (&mask_node.discriminator, value.as_object()) {
+                (Some(discriminator), Some(object)) => match discriminator.select(object) {
+                    Some(branch) => pending.push(branch),
+                    None => match self.unknown_discriminator {
+                        UnknownDiscriminator::KeepBase => {}
+                        UnknownDiscriminator::Drop => return Ok(Applicable::Drop),
+                        UnknownDiscriminator::Error => {
+                            return Err(MaskError::UnknownDiscriminator {
+                                pointer: location.pointer(),
+                                property: discriminator.property.clone(),
+                                value: object
+                                    .get(&discriminator.property)
+                                    .cloned()
+                                    .unwrap_or_default(),
+                            })
+                        }
+                    },
+                },
+                _ => {
+                    for (branches, exclusive) in
+                        [(&mask_node.any_of, false), (&mask_node.one_of, true)]
+                    {
+                        match self.select_branches(branches, exclusive, value)? {
+                            Some(selected) => pending.extend(selected),
+                            None if self.unmatched_branches == UnmatchedBranches::Drop => {
+                                return Ok(Applicable::Drop)
+                            }
+                            None => return Ok(Applicable::Keep),
+                        }
+                    }
+                }
+            }
+
+            if let Some(object) = value.as_object() {
+                pending.extend(
+                    mask_node
+                        .dependent_schemas
+                        .iter()
+                        .filter(|(key, _)| object.contains_key(key))
+                        .map(|(_, dependency)| *dependency),
+                );
+            }
+
+            if let Some(conditional) = &mask_node.conditional {
+                if conditional.condition.is_valid(value, &self.mask.compiler)? {
+                    pending.push(conditional.condition.node);
+                    pending.extend(conditional.then);
+                } else {
+                    pending.extend(conditional.otherwise);
+                }
+            }
+        }
+
+        Ok(Applicable::Nodes(
+            visited.into_iter().map(|id| self.mask.node(id)).collect(),
+        ))
+    }
+
+    // None means the value matched none of the branches (or more than one exclusive branch), and
+    // should be kept or dropped as is.
+    fn select_branches(
+        &self,
+        branches: &[Branch],
+        exclusive: bool,
+        value: &Value,
+    ) -> Result<Option<Vec<NodeId>>, MaskError> {
+        // A false branch never matches, so it's left out of the union rather than dropping the
+        // value.
+        let union = || {
+            branches
+                .iter()
+                .rev()
+                .map(|branch| branch.node)
+                .filter(|node| !self.mask.node(*node).drop)
+                .collect()
+        };
+
+        if self.branch_selection == BranchSelection::Union || branches.is_empty() {
+            return Ok(Some(union()));
+        }
+
+        let mut matching = Vec::new();
+        for branch in branches.iter().rev() {
+            if branch.is_valid(value, &self.mask.compiler)? {
+                matching.push(branch.node);
+            }
+        }
+
+        Ok(
+            if matching.is_empty() || (exclusive && matching.len() > 1) {
+                match self.unmatched_branches {
+                    UnmatchedBranches::Union => Some(union()),
+                    UnmatchedBranches::Keep | UnmatchedBranches::Drop => None,
+                }
+            } else {
+                Some(matching)
+            },
+        )
+    }
+
+    fn mask_object(
+        &self,
+        object: &mut Map<String, Value>,
+        mask_nodes: &[&MaskNode],
+        location: Location,
+        input: &Value,
+        report: &mut MaskReport,
+    ) -> Result<(), MaskError> {
+        rename_properties(object, mask_nodes);
+        move_properties(object, mask_nodes, input);
+
+        if self.fill_defaults {
+            self.fill_defaults(object, mask_nodes);
+        }
+
+        let mut result = Ok(());
+
+        object.retain(|key, value| {
+            if result.is_err() {
+                return true;
+            }
+
+            let mut children = Vec::new();
+            let mut keep = false;
+            let mut evaluated = false;
+
+            for mask_node in mask_nodes {
+                if let Some(keep_node) = mask_node.property_masks(key, &mut children) {
+                    keep |= keep_node;
+                    evaluated = true;
+                }
+            }
+
+            // A key that no applicable node evaluated is left to unevaluatedProperties, and is
+            // dropped without it, because the point of the mask is to only keep what the schema
+            // describes. The applicable nodes are flattened, so unevaluatedProperties sees the keys
+            // evaluated by every applicable node rather than only its own subschemas.
+            if !evaluated {
+                for mask_node in mask_nodes {
+                    if let Some(unevaluated_properties) = mask_node.unevaluated_properties {
+                        keep |= unevaluated_properties.apply(&mut children);
+                    }
+                }
+            }
+
+            if children.is_empty() {
+                return keep;
+            }
+
+            match self.mask_value(
+                value,
+                &children,
+                Location::Key(&location, key),
+                input,
+                report,
+            ) {
+                Ok(keep) => keep,
+                Err(error) => {
+                    result = Err(error);
+                    true
+                }
+            }
+        });
+
+        result
+    }
+
+    // Inserts the missing properties that have a default, or are required objects. The inserted
+    // values are then masked, and filled, like any other property.
+    fn fill_defaults(&self, object: &mut Map<String, Value>, mask_nodes: &[&MaskNode]) {
+        for mask_node in mask_nodes {
+            for (key, &child) in &mask_node.properties {
+                if object.contains_key(key) {
+                    continue;
+                }
+
+                let child_node = self.mask.node(child);
+
+                if let Some(default) = &child_node.default {
+                    object.insert(key.clone(), default.clone());
+                } else if child_node.object
+                    && mask_nodes
+                        .iter()
+                        .any(|mask_node| mask_node.required.contains(key))
+                {
+                    object.insert(key.clone(), Value::Object(Map::new()));
+                }
+            }
+        }
+    }
+
+    // Masks the value in place, returns whether the value should be kept.
+    fn mask_value(
+        &self,
+        value: &mut Value,
+        ids: &[NodeId],
+        location: Location,
+        input: &Value,
+        report: &mut MaskReport,
+    ) -> Result<bool, MaskError> {
+        let mask_nodes = match self.applicable_nodes(ids, value, location)? {
+            Applicable::Nodes(mask_nodes) => mask_nodes,
+            Applicable::Keep => return Ok(true),
+            Applicable::Drop => return Ok(false),
+        };
+
+        if mask_nodes.iter().any(|mask_node| mask_node.drop) {
+            return Ok(false);
+        }
+
+        match self.direction {
+            Direction::Any => {}
+            Direction::Request | Direction::StrictRequest
+                if mask_nodes.iter().any(|mask_node| mask_node.read_only) =>
+            {
+                if self.direction == Direction::StrictRequest {
+                    return Err(MaskError::ReadOnly {
+                        pointer: location.pointer(),
+                    });
+                }
+
+                return Ok(false);
+            }
+            Direction::Response if mask_nodes.iter().any(|mask_node| mask_node.write_only) => {
+                return Ok(false)
+            }
+            _ => {}
+        }
+
+        // Only the schemas of the value itself are checked, not the subschemas that apply to it,
+        // the same way deprecated properties are excluded.
+        if self.report_deprecated && ids.iter().any(|id| self.mask.node(*id).deprecated) {
+            report.deprecated.push(location.pointer());
+        }
+
+        if self.coerce_types && !coerce_value(value, &declared_types(&mask_nodes)) {
+            report.unconverted.push(location.pointer());
+        }
+
+        if !matches!(location, Location::Root) && self.type_mismatch != TypeMismatch::Keep {
+            let types = declared_types(&mask_nodes);
+
+            if !is_type(value, &types) {
+                if self.type_mismatch == TypeMismatch::Error {
+                    return Err(type_mismatch(value, &types, location));
+                }
+
+                return Ok(false);
+            }
+        }
+
+        if !self.mask_enum(value, &mask_nodes, location)? {
+            return Ok(false);
+        }
+
+        // A discriminator only applies to objects, so an object is masked by its node even when the
+        // node has no type, otherwise an unknown discriminator value would keep the whole object.
+        match value {
+            Value::Object(object)
+                if mask_nodes
+                    .iter()
+                    .any(|mask_node| mask_node.object || mask_node.discriminator.is_some()) =>
+            {
+                self.mask_object(object, &mask_nodes, location, input, report)?
+            }
+            Value::Array(array) if mask_nodes.iter().any(|mask_node| mask_node.array) => {
+                self.mask_array(array, &mask_nodes, location, input, report)?
+            }
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
+    // Applies the enum mismatch policy to a scalar value that isn't allowed by the enums (or consts)
+    // of the nodes, returns whether the value should be kept.
+    fn mask_enum(
+        &self,
+        value: &mut Value,
+        mask_nodes: &[&MaskNode],
+        location: Location,
+    ) -> Result<bool, MaskError> {
+        if value.is_object() || value.is_array() {
+            return Ok(true);
+        }
+
+        let mut enum_nodes = mask_nodes
+            .iter()
+            .filter(|mask_node| mask_node.allowed_values.is_some())
+            .peekable();
+
+        if enum_nodes.peek().is_none() || self.allows_value(value, mask_nodes) {
+            return Ok(true);
+        }
+
+        // The schema's own replacements for the value take precedence over the policy.
+        let replacement = mask_nodes
+            .iter()
+            .find_map(|mask_node| mask_node.enum_map.get(value.as_str()?))
+            .or_else(|| {
+                mask_nodes
+                    .iter()
+                    .find_map(|mask_node| mask_node.enum_fallback.as_ref())
+            });
+
+        if let Some(replacement) = replacement {
+            *value = replacement.clone();
+            return Ok(true);
+        }
+
+        let enum_mismatch = enum_nodes
+            .find_map(|mask_node| self.field_enum_mismatch.get(&mask_node.pointer))
+            .unwrap_or(&self.enum_mismatch);
+
+        match enum_mismatch {
+            EnumMismatch::Keep => Ok(true),
+            EnumMismatch::Drop => Ok(false),
+            EnumMismatch::Replace(fallback) => {
+                *value = fallback.clone();
+                Ok(true)
+            }
+            EnumMismatch::Error => Err(MaskError::EnumMismatch {
+                pointer: location.pointer(),
+                value: value.clone(),
+            }),
+        }
+    }
+
+    // Whether the value is allowed by the enums of the nodes. The nodes a node reaches through allOf
+    // (or a reference next to other keywords) narrow the values it allows, while the others (e.g.
+    // anyOf branches) are a union, so any of them may allow the value.
+    fn allows_value(&self, value: &Value, mask_nodes: &[&MaskNode]) -> bool {
+        let closures: Vec<_> = mask_nodes
+            .iter()
+            .map(|mask_node| self.all_of_closure(mask_node))
+            .collect();
+        let allows = |mask_node: &&MaskNode| {
+            mask_node.allowed_values.as_ref().map(|allowed_values| {
+                allowed_values
+                    .iter()
+                    .any(|allowed_value| json_equal(allowed_value, value))
+            })
+        };
+
+        // Only the nodes that no other node reaches through allOf decide, otherwise the nodes they
+        // reach would allow values on their own.
+        let verdicts: Vec<bool> = closures
+            .iter()
+            .enumerate()
+            .filter(|(index, closure)| {
+                !closures.iter().enumerate().any(|(other, other_closure)| {
+                    other != *index
+                        && other_closure[1..]
+                            .iter()
+                            .any(|mask_node| std::ptr::eq(*mask_node, closure[0]))
+                })
+            })
+            .filter_map(|(_, closure)| {
+                let allowed: Vec<bool> = closure.iter().filter_map(allows).collect();
+                (!allowed.is_empty()).then(|| allowed.into_iter().all(|allowed| allowed))
+            })
+            .collect();
+
+        // When every node is reached by another one (i.e. allOf forms a cycle), any of them may
+        // allow the value.
+        if verdicts.is_empty() {
+            return mask_nodes
+                .iter()
+                .any(|mask_node| allows(mask_node) == Some(true));
+        }
+
+        verdicts.contains(&true)
+    }
+
+    // The node and every node it reaches through allOf.
+    fn all_of_closure<'m>(&'m self, mask_node: &'m MaskNode) -> Vec<&'m MaskNode> {
+        let mut closure = vec![mask_node];
+        let mut next = 0;
+
+        while let Some(&mask_node) = closure.get(next) {
+            for &id in &mask_node.all_of {
+                let child = self.mask.node(id);
+
+                if !closure.iter().any(|node| std::ptr::eq(*node, child)) {
+                    closure.push(child);
+                }
+            }
+
+            next += 1;
+        }
+
+        closure
+    }
+
+    fn mask_array(
+        &self,
+        array: &mut Vec<Value>,
+        mask_nodes: &[&MaskNode],
+        location: Location,
+        input: &Value,
+        report: &mut MaskReport,
+    ) -> Result<(), MaskError> {
+        let tuple_length = mask_nodes
+            .iter()
+            .map(|mask_node| mask_node.prefix_items.len())
+            .max()
+            .unwrap_or_default();
+        let is_tuple = tuple_length > 0;
+
+        if is_tuple && self.extra_items == ExtraItems::Drop {
+            array.truncate(tuple_length);
+        }
+
+        let mut keep = Vec::with_capacity(array.len());
+
+        for (index, element) in array.iter_mut().enumerate() {
+            if index >= tuple_length && is_tuple && self.extra_items == ExtraItems::Keep {
+                keep.push(true);
+                continue;
+            }
+
+            let element_masks: Vec<NodeId> = mask_nodes
+                .iter()
+                .filter_map(|mask_node| match mask_node.prefix_items.get(index) {
+                    Some(prefix_item) => Some(*prefix_item),
+                    None => mask_node.items,
+                })
+                .collect();
+
+            keep.push(
+                element_masks.is_empty()
+                    || self.mask_value(
+                        element,
+                        &element_masks,
+                        Location::Index(&location, index),
+                        input,
+                        report,
+                    )?,
+            );
+        }
+
+        let mut keep = keep.into_iter();
+        array.retain(|_| keep.next().unwrap());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::str::FromStr;
+    use uuid::{uuid, Uuid};
+
+    fn get_masker(schema: &str) -> JsonMasker {
+        JsonMasker::new(Mask::try_from(&get_valid_schema(schema).unwrap()).unwrap())
+    }
+
+    fn get_valid_schema(schema: &str) -> Result<ValidJsonSchema, ParseError> {
+        ValidJsonSchema::new(serde_json::from_str(schema).unwrap())
+    }
+
+    const NONCE: u64 = 12345;
+    const VM_ID: Uuid = uuid!("19e656a1-b9ca-4344-88c9-ef0a6b5999e5");
+    const FOO: &str = "foo-value";
+    const BAR: &str = "bar-value";
+    const CREATED_ON: &str = "2023-07-28 17:59:14Z";
+    const EXPIRES_ON: &str = "2023-07-28 20:59:14Z";
+
+    fn get_metadata_json() -> Value {
+        json!({
+            "nonce": 12345,
+            "vmId": VM_ID
+        })
+    }
+
+    fn get_foobar_json() -> Value {
+        json!({
+            "foo": FOO,
+            "bar": BAR
+        })
+    }
+
+    fn get_mixed_json() -> Value {
+        json!({
+            "nonce": NONCE,
+            "foo": FOO
+        })
+    }
+
+    #[test]
+    // There are lots of unwraps in the the masker parsing because it expects a valid schema.
+    // This test ensures that the valid schema wrapper is holding these invariants correctly.
+    pub fn bad_schema_no_panic() {
+        assert!(get_valid_schema(INVALID_SCHEMA_OBJECT).is_err());
+        assert!(get_valid_schema(INVALID_NESTED_SCHEMA).is_err());
+        assert!(get_valid_schema(INVALID_SCHEMA_NULL_TYPE).is_err());
+        assert!(get_valid_schema(INVALID_SCHEMA_NULL_PROPERTIES).is_err());
+        assert!(get_valid_schema(RANDOM_JSON).is_err());
+    }
+
+    #[test]
+    // Schema validator only checks that provided fields are valid, but missing information is
+    // allowed.
+    pub fn schema_missing_expected_fields_no_panic() {
+        get_masker(INVALID_SCHEMA_EMPTY_PROPERTIES);
+        get_masker(INVALID_SCHEMA_NO_TYPE);
+        get_masker(INVALID_SCHEMA_NO_PROPERTIES);
+    }
+
+    #[test]
+    pub fn mask_json_simple_schema_exact_match() {
+        let mut json = get_metadata_json();
+
+        get_masker(SIMPLE_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(NONCE, json["nonce"].as_u64().unwrap());
+        assert_eq!(
+            VM_ID,
+            Uuid::from_str(json["vmId"].as_str().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    pub fn mask_json_simple_schema_all_filtered() {
+        let mut json = get_foobar_json();
+
+        get_masker(SIMPLE_SCHEMA).mask(&mut json).unwrap();
+
+        assert!(json.get("foo").is_none());
+        assert!(json.get("bar").is_none());
+    }
+
+    #[test]
+    pub fn mask_json_simple_schema_partially_filtered() {
+        let mut json = get_mixed_json();
+
+        get_masker(SIMPLE_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(NONCE, json["nonce"].as_u64().unwrap());
+        assert!(json.get("foo").is_none());
+    }
+
+    #[test]
+    pub fn mask_json_nested_schema_exact_match() {
+        let mut json = get_metadata_json();
+
+        let timestamp = json!({
+            "createdOn": CREATED_ON,
+            "expiresOn": EXPIRES_ON
+        });
+
+        json["timestamp"] = timestamp;
+
+        get_masker(NESTED_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(NONCE, json["nonce"].as_u64().unwrap());
+        assert_eq!(
+            VM_ID,
+            Uuid::from_str(json["vmId"].as_str().unwrap()).unwrap()
+        );
+        assert_eq!(CREATED_ON, json["timestamp"]["createdOn"].as_str().unwrap());
+        assert_eq!(EXPIRES_ON, json["timestamp"]["expiresOn"].as_str().unwrap());
+    }
+
+    #[test]
+    pub fn mask_json_nested_schema_all_filtered() {
+        let mut json = get_foobar_json();
+
+        let nested_object = json!({
+            "foo": FOO,
+            "bar": BAR
+        });
+
+        json["foobar"] = nested_object;
+
+        get_masker(NESTED_SCHEMA).mask(&mut json).unwrap();
+
+        assert!(json.get("foo").is_none());
+        assert!(json.get("bar").is_none());
+        assert!(json.get("foobar").is_none());
+    }
+
+    #[test]
+    pub fn mask_json_nested_schema_partially_filtered() {
+        let mut json = get_mixed_json();
+        let nested_object = json!({
+            "createdOn": CREATED_ON,
+            "bar": BAR
+        });
+
+        json["timestamp"] = nested_object;
+
+        get_masker(NESTED_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(NONCE, json["nonce"].as_u64().unwrap());
+        assert!(json.get("foo").is_none());
+        assert!(json.get("timestamp").unwrap().is_object());
+        assert_eq!(CREATED_ON, json["timestamp"]["createdOn"].as_str().unwrap());
+        assert!(json["timestamp"].get("bar").is_none());
+    }
+
+    #[test]
+    pub fn mask_json_array_schema_items_filtered() {
+        let mut json = json!({
+            "vms": [get_mixed_json(), get_metadata_json()],
+            "tags": ["a", "b"]
+        });
+
+        get_masker(ARRAY_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(2, json["vms"].as_array().unwrap().len());
+        assert_eq!(NONCE, json["vms"][0]["nonce"].as_u64().unwrap());
+        assert!(json["vms"][0].get("foo").is_none());
+        assert_eq!(
+            VM_ID,
+            Uuid::from_str(json["vms"][1]["vmId"].as_str().unwrap()).unwrap()
+        );
+        assert_eq!(json!(["a", "b"]), json["tags"]);
+    }
+
+    #[test]
+    pub fn mask_json_array_schema_nested_arrays_filtered() {
+        let mut json = json!({
+            "grid": [[get_foobar_json(), get_mixed_json()], [get_metadata_json()]]
+        });
+
+        get_masker(ARRAY_SCHEMA).mask(&mut json).unwrap();
+
+        assert!(json["grid"][0][0].as_object().unwrap().is_empty());
+        assert_eq!(json!({ "nonce": NONCE }), json["grid"][0][1]);
+        assert_eq!(get_metadata_json(), json["grid"][1][0]);
+    }
+
+    #[test]
+    pub fn mask_json_array_schema_object_value_kept() {
+        let mut json = json!({
+            "vms": get_foobar_json()
+        });
+
+        get_masker(ARRAY_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(get_foobar_json(), json["vms"]);
+    }
+
+    fn get_tuple_json() -> Value {
+        json!({
+            "events": [
+                CREATED_ON,
+                get_mixed_json(),
+                get_foobar_json(),
+                get_mixed_json()
+            ]
+        })
+    }
+
+    #[test]
+    pub fn mask_json_tuple_schema_prefix_items_filtered() {
+        for schema in [TUPLE_SCHEMA_PREFIX_ITEMS, TUPLE_SCHEMA_ARRAY_ITEMS] {
+            let mut json = get_tuple_json();
+
+            get_masker(schema).mask(&mut json).unwrap();
+
+            assert_eq!(4, json["events"].as_array().unwrap().len());
+            assert_eq!(CREATED_ON, json["events"][0].as_str().unwrap());
+            assert_eq!(json!({ "nonce": NONCE }), json["events"][1]);
+            assert_eq!(json!({ "foo": FOO }), json["events"][2]);
+            assert_eq!(json!({ "foo": FOO }), json["events"][3]);
+        }
+    }
+
+    #[test]
+    pub fn mask_json_tuple_schema_extra_items_kept() {
+        let mut json = get_tuple_json();
+
+        get_masker(TUPLE_SCHEMA_PREFIX_ITEMS)
+            .with_extra_items(ExtraItems::Keep)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(json!({ "nonce": NONCE }), json["events"][1]);
+        assert_eq!(get_foobar_json(), json["events"][2]);
+        assert_eq!(get_mixed_json(), json["events"][3]);
+    }
+
+    #[test]
+    pub fn mask_json_tuple_schema_extra_items_dropped() {
+        let mut json = get_tuple_json();
+
+        get_masker(TUPLE_SCHEMA_ARRAY_ITEMS)
+            .with_extra_items(ExtraItems::Drop)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(2, json["events"].as_array().unwrap().len());
+        assert_eq!(json!({ "nonce": NONCE }), json["events"][1]);
+    }
+
+    #[test]
+    pub fn mask_json_tuple_schema_closed() {
+        for schema in [
+            TUPLE_SCHEMA_CLOSED_PREFIX_ITEMS,
+            TUPLE_SCHEMA_CLOSED_ARRAY_ITEMS,
+        ] {
+            let mut json = json!({ "events": [FOO, { "secret": 1 }, BAR] });
+
+            get_masker(schema).mask(&mut json).unwrap();
+
+            assert_eq!(json!({ "events": [FOO] }), json);
+        }
+    }
+
+    #[test]
+    pub fn mask_json_reference_schema_definitions_resolved() {
+        for schema in [REFERENCE_SCHEMA_DEFINITIONS, REFERENCE_SCHEMA_DEFS] {
+            let mut json = get_mixed_json();
+            json["timestamp"] = json!({
+                "createdOn": CREATED_ON,
+                "bar": BAR
+            });
+            json["history"] = json!([{ "expiresOn": EXPIRES_ON, "foo": FOO }]);
+
+            get_masker(schema).mask(&mut json).unwrap();
+
+            assert_eq!(NONCE, json["nonce"].as_u64().unwrap());
+            assert!(json.get("foo").is_none());
+            assert_eq!(json!({ "createdOn": CREATED_ON }), json["timestamp"]);
+            assert_eq!(json!([{ "expiresOn": EXPIRES_ON }]), json["history"]);
+        }
+    }
+
+    #[test]
+    pub fn dangling_reference_is_parse_error() {
+        let schema = get_valid_schema(INVALID_SCHEMA_DANGLING_REFERENCE).unwrap();
+
+        assert!(matches!(
+            Mask::try_from(&schema),
+            Err(ParseError::InvalidReference(reference)) if reference == "#/definitions/Missing"
+        ));
+    }
+
+    #[test]
+    pub fn mask_json_reference_schema_uris_resolved() {
+        let mut json = get_mixed_json();
+        json["timestamp"] = json!({ "createdOn": CREATED_ON, "nonce": NONCE, "bar": BAR });
+        json["history"] = json!([{ "expiresOn": EXPIRES_ON, "foo": FOO }]);
+        json["remote"] = json!({ "foo": FOO });
+
+        get_masker(REFERENCE_SCHEMA_URIS).mask(&mut json).unwrap();
+
+        assert_eq!(
+            json!({
+                "nonce": NONCE,
+                "timestamp": { "createdOn": CREATED_ON, "nonce": NONCE },
+                "history": [{ "expiresOn": EXPIRES_ON }],
+                "remote": { "foo": FOO }
+            }),
+            json
+        );
+    }
+
+    fn get_folder_json(depth: usize) -> Value {
+        let mut folder = json!({ "name": FOO, "foo": FOO, "subfolders": [] });
+
+        for _ in 0..depth {
+            folder = json!({
+                "name": FOO,
+                "bar": BAR,
+                "subfolders": [folder, { "name": BAR, "subfolders": [] }]
+            });
+        }
+
+        folder
+    }
+
+    #[test]
+    pub fn mask_json_reference_schema_siblings() {
+        let mut json = json!({
+            "vm": { "nonce": NONCE, "vmId": VM_ID, "extra": FOO, "foo": FOO }
+        });
+
+        get_masker(REFERENCE_SCHEMA_SIBLINGS)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(
+            json!({ "vm": { "nonce": NONCE, "vmId": VM_ID, "extra": FOO } }),
+            json
+        );
+    }
+
+    #[test]
+    pub fn mask_json_reference_schema_siblings_ignored_before_2019_09() {
+        let mut json = json!({
+            "vm": { "nonce": NONCE, "vmId": VM_ID, "extra": FOO, "foo": FOO }
+        });
+
+        let schema = REFERENCE_SCHEMA_SIBLINGS
+            .replace(
+                "https://json-schema.org/draft/2020-12/schema",
+                "http://json-schema.org/draft-07/schema",
+            )
+            .replace("$defs", "definitions");
+        get_masker(&schema).mask(&mut json).unwrap();
+
+        assert_eq!(json!({ "vm": { "nonce": NONCE, "vmId": VM_ID } }), json);
+    }
+
+    #[test]
+    pub fn mask_json_boolean_schema() {
+        let mut json = json!({
+            "allowed": { "foo": FOO },
+            "forbidden": { "foo": FOO },
+            "either": { "foo": FOO, "bar": BAR }
+        });
+
+        get_masker(BOOLEAN_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(
+            json!({ "allowed": { "foo": FOO }, "either": { "foo": FOO } }),
+            json
+        );
+    }
+
+    #[test]
+    pub fn mask_json_recursive_schema_definition_reference() {
+        let depth = 50;
+        let mut json = get_folder_json(depth);
+
+        get_masker(RECURSIVE_SCHEMA_DEFINITION)
+            .mask(&mut json)
+            .unwrap();
+
+        let mut folder = &json;
+        for _ in 0..depth {
+            assert_eq!(FOO, folder["name"].as_str().unwrap());
+            assert!(folder.get("bar").is_none());
+            assert_eq!(
+                json!({ "name": BAR, "subfolders": [] }),
+                folder["subfolders"][1]
+            );
+
+            folder = &folder["subfolders"][0];
+        }
+        assert_eq!(json!({ "name": FOO, "subfolders": [] }), *folder);
+    }
+
+    #[test]
+    pub fn mask_json_recursive_schema_root_reference() {
+        let mut json = get_metadata_json();
+        json["foo"] = json!(FOO);
+        json["parent"] = get_mixed_json();
+        json["parent"]["parent"] = get_metadata_json();
+        json["parent"]["parent"]["bar"] = json!(BAR);
+
+        get_masker(RECURSIVE_SCHEMA_ROOT).mask(&mut json).unwrap();
+
+        assert!(json.get("foo").is_none());
+        assert_eq!(NONCE, json["parent"]["nonce"].as_u64().unwrap());
+        assert!(json["parent"].get("foo").is_none());
+        assert_eq!(get_metadata_json(), json["parent"]["parent"]);
+    }
+
+    #[test]
+    pub fn circular_reference_chain_is_parse_error() {
+        let schema = get_valid_schema(INVALID_SCHEMA_CIRCULAR_REFERENCE).unwrap();
+
+        assert!(matches!(
+            Mask::try_from(&schema),
+            Err(ParseError::InvalidJsonSchema(_))
+        ));
+    }
+
+    #[test]
+    pub fn mask_json_all_of_schema_properties_merged() {
+        let mut json = get_metadata_json();
+        json["foo"] = json!(FOO);
+        json["bar"] = json!(BAR);
+        json["timestamp"] = json!({
+            "createdOn": CREATED_ON,
+            "expiresOn": EXPIRES_ON,
+            "bar": BAR
+        });
+
+        get_masker(ALL_OF_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(NONCE, json["nonce"].as_u64().unwrap());
+        assert!(json.get("vmId").is_none());
+        assert_eq!(FOO, json["foo"].as_str().unwrap());
+        assert!(json.get("bar").is_none());
+        assert_eq!(
+            json!({ "createdOn": CREATED_ON, "expiresOn": EXPIRES_ON }),
+            json["timestamp"]
+        );
+    }
+
+    #[test]
+    pub fn mask_json_all_of_schema_nested_reference() {
+        let mut json = json!({
+            "foo": FOO,
+            "child": {
+                "nonce": NONCE,
+                "foo": FOO,
+                "bar": BAR,
+                "child": get_mixed_json()
+            }
+        });
+
+        get_masker(ALL_OF_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(
+            json!({ "nonce": NONCE, "foo": FOO }),
+            json["child"]["child"]
+        );
+        assert!(json["child"].get("bar").is_none());
+    }
+
+    fn get_polymorphic_json(resource: Value) -> Value {
+        json!({
+            "nonce": NONCE,
+            "resource": resource
+        })
+    }
+
+    #[test]
+    pub fn mask_json_one_of_schema_union() {
+        let mut json = get_polymorphic_json(json!({
+            "vmId": VM_ID,
+            "size": 1,
+            "foo": FOO
+        }));
+
+        get_masker(ONE_OF_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(json!({ "vmId": VM_ID, "size": 1 }), json["resource"]);
+    }
+
+    #[test]
+    pub fn mask_json_one_of_schema_matching_branch() {
+        let masker = get_masker(ONE_OF_SCHEMA).with_branch_selection(BranchSelection::Matching);
+        let mut vm = get_polymorphic_json(json!({ "kind": "vm", "vmId": VM_ID, "size": 1 }));
+        let mut disk = get_polymorphic_json(json!({ "kind": "disk", "vmId": VM_ID, "size": 1 }));
+
+        masker.mask(&mut vm).unwrap();
+        masker.mask(&mut disk).unwrap();
+
+        assert_eq!(json!({ "kind": "vm", "vmId": VM_ID }), vm["resource"]);
+        assert_eq!(json!({ "kind": "disk", "size": 1 }), disk["resource"]);
+    }
+
+    #[test]
+    pub fn mask_json_one_of_schema_id_with_empty_fragment() {
+        let schema = ONE_OF_SCHEMA.replace(
+            r#""title": "One Of Schema","#,
+            r#""$id": "http://example.com/s.json#", "title": "One Of Schema","#,
+        );
+        let mut json = get_polymorphic_json(json!({ "kind": "vm", "vmId": VM_ID, "size": 1 }));
+
+        get_masker(&schema)
+            .with_branch_selection(BranchSelection::Matching)
+            .with_unmatched_branches(UnmatchedBranches::Drop)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(json!({ "kind": "vm", "vmId": VM_ID }), json["resource"]);
+    }
+
+    #[test]
+    pub fn mask_json_one_of_schema_unmatched_branches() {
+        let unmatched = json!({ "kind": "potato", "vmId": VM_ID, "foo": FOO });
+        let ambiguous = json!({ "vmId": VM_ID, "size": 1, "foo": FOO });
+
+        let masker = |unmatched_branches| {
+            get_masker(ONE_OF_SCHEMA)
+                .with_branch_selection(BranchSelection::Matching)
+                .with_unmatched_branches(unmatched_branches)
+        };
+
+        let mut json = get_polymorphic_json(unmatched.clone());
+        masker(UnmatchedBranches::Union).mask(&mut json).unwrap();
+        assert_eq!(json!({ "kind": "potato", "vmId": VM_ID }), json["resource"]);
+
+        let mut json = get_polymorphic_json(ambiguous.clone());
+        masker(UnmatchedBranches::Keep).mask(&mut json).unwrap();
+        assert_eq!(ambiguous, json["resource"]);
+
+        let mut json = get_polymorphic_json(unmatched);
+        masker(UnmatchedBranches::Drop).mask(&mut json).unwrap();
+        assert_eq!(json!({ "nonce": NONCE }), json);
+    }
+
+    #[test]
+    pub fn mask_json_any_of_schema_matching_branches() {
+        let mut json = json!([
+            { "createdOn": CREATED_ON, "foo": FOO, "bar": BAR },
+            { "expiresOn": EXPIRES_ON, "foo": FOO, "bar": BAR },
+            { "createdOn": CREATED_ON, "expiresOn": EXPIRES_ON, "foo": FOO, "bar": BAR }
+        ]);
+        json = json!({ "history": json });
+
+        get_masker(ANY_OF_SCHEMA)
+            .with_branch_selection(BranchSelection::Matching)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(
+            json!([
+                { "createdOn": CREATED_ON, "foo": FOO },
+                { "expiresOn": EXPIRES_ON, "bar": BAR },
+                { "createdOn": CREATED_ON, "expiresOn": EXPIRES_ON, "foo": FOO, "bar": BAR }
+            ]),
+            json["history"]
+        );
+    }
+
+    #[test]
+    pub fn mask_json_any_of_schema_escaped_key() {
+        let mut json = json!({
+            "a b": [
+                { "createdOn": CREATED_ON, "foo": FOO, "bar": BAR },
+                { "expiresOn": EXPIRES_ON, "foo": FOO, "bar": BAR }
+            ]
+        });
+
+        get_masker(ESCAPED_KEY_SCHEMA)
+            .with_branch_selection(BranchSelection::Matching)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(
+            json!({
+                "a b": [
+                    { "createdOn": CREATED_ON, "foo": FOO },
+                    { "expiresOn": EXPIRES_ON, "bar": BAR }
+                ]
+            }),
+            json
+        );
+    }
+
+    #[test]
+    pub fn mask_json_conditional_schema_escaped_key() {
+        let mut json = json!({ "a%25": { "k": 1, "foo": FOO } });
+
+        get_masker(ESCAPED_KEY_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(json!({ "a%25": { "k": 1 } }), json);
+    }
+
+    #[test]
+    pub fn mask_json_conditional_schema_then_branch() {
+        let mut json = json!({
+            "kind": "vm",
+            "nonce": NONCE,
+            "vmId": VM_ID,
+            "size": 1
+        });
+
+        get_masker(CONDITIONAL_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(json!({ "kind": "vm", "nonce": NONCE, "vmId": VM_ID }), json);
+    }
+
+    #[test]
+    pub fn mask_json_conditional_schema_id_with_empty_fragment() {
+        let schema = CONDITIONAL_SCHEMA.replace(
+            r#""title": "Conditional Schema","#,
+            r#""$id": "http://example.com/s.json#", "title": "Conditional Schema","#,
+        );
+        let mut json = json!({ "kind": "vm", "vmId": VM_ID, "size": 1 });
+
+        get_masker(&schema).mask(&mut json).unwrap();
+
+        assert_eq!(json!({ "kind": "vm", "vmId": VM_ID }), json);
+    }
+
+    #[test]
+    pub fn mask_json_conditional_schema_else_branch() {
+        let mut json = json!([
+            { "kind": "disk", "nonce": NONCE, "vmId": VM_ID, "size": 1 },
+            { "nonce": NONCE, "vmId": VM_ID, "size": 1 }
+        ]);
+        json = json!({ "kind": "vm", "nonce": NONCE, "children": json });
+
+        get_masker(CONDITIONAL_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(
+            json!({ "kind": "disk", "nonce": NONCE, "size": 1 }),
+            json["children"][0]
+        );
+        assert_eq!(json!({ "nonce": NONCE, "size": 1 }), json["children"][1]);
+    }
+
+    #[test]
+    pub fn mask_json_additional_properties_schema_kept() {
+        let mut json = get_mixed_json();
+        json["labels"] = get_foobar_json();
+        json["timestamp"] = json!({ "createdOn": CREATED_ON, "bar": BAR });
+
+        get_masker(ADDITIONAL_PROPERTIES_SCHEMA)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(get_foobar_json(), json["labels"]);
+        assert_eq!(json!({ "createdOn": CREATED_ON }), json["timestamp"]);
+        assert!(json.get("foo").is_none());
+    }
+
+    #[test]
+    pub fn mask_json_additional_properties_schema_masked() {
+        let mut json = get_mixed_json();
+        json["vms"] = json!({
+            "first": get_metadata_json(),
+            "second": { "nonce": NONCE, "foo": FOO }
+        });
+
+        get_masker(ADDITIONAL_PROPERTIES_SCHEMA)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(get_metadata_json(), json["vms"]["first"]);
+        assert_eq!(json!({ "nonce": NONCE }), json["vms"]["second"]);
+    }
+
+    #[test]
+    pub fn mask_json_pattern_properties_schema_matched() {
+        let mut json = json!({
+            "nonce": NONCE,
+            "x-ms-foo": FOO,
+            "x-bar": BAR,
+            "names": {
+                "en-US": { "name": FOO, "bar": BAR },
+                "fr-FR": { "name": BAR },
+                "english": { "name": FOO }
+            }
+        });
+
+        get_masker(PATTERN_PROPERTIES_SCHEMA)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(
+            json!({
+                "nonce": NONCE,
+                "x-ms-foo": FOO,
+                "names": {
+                    "en-US": { "name": FOO },
+                    "fr-FR": { "name": BAR },
+                    "english": { "name": FOO }
+                }
+            }),
+            json
+        );
+    }
+
+    #[test]
+    pub fn mask_json_pattern_properties_schema_precedence() {
+        let mut json = json!({
+            "nonce": NONCE,
+            "x-ms-timestamp": { "createdOn": CREATED_ON, "expiresOn": EXPIRES_ON, "bar": BAR },
+            "names": {
+                "default": { "name": FOO, "bar": BAR }
+            }
+        });
+
+        get_masker(PATTERN_PROPERTIES_SCHEMA)
+            .mask(&mut json)
+            .unwrap();
+
+        // Properties and pattern properties both apply to a key, and the union is kept.
+        assert_eq!(
+            json!({ "createdOn": CREATED_ON, "expiresOn": EXPIRES_ON }),
+            json["x-ms-timestamp"]
+        );
+        // Additional properties only apply to keys that didn't match.
+        assert_eq!(json!({ "name": FOO, "bar": BAR }), json["names"]["default"]);
+    }
+
+    #[test]
+    pub fn invalid_pattern_is_parse_error() {
+        let mut schema: Value = serde_json::from_str(PATTERN_PROPERTIES_SCHEMA).unwrap();
+        schema["properties"]["names"]["patternProperties"] = json!({ "(?<": {} });
+
+        assert!(ValidJsonSchema::new(schema)
+            .and_then(|schema| Mask::try_from(&schema))
+            .is_err());
+    }
+
+    #[test]
+    pub fn mask_json_inferred_type_schema_filtered() {
+        let mut json = get_mixed_json();
+        json["timestamp"] = json!({ "createdOn": CREATED_ON, "bar": BAR });
+        json["metadata"] = get_mixed_json();
+        json["history"] = json!([{ "expiresOn": EXPIRES_ON, "foo": FOO }]);
+
+        get_masker(INFERRED_TYPE_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(
+            json!({
+                "nonce": NONCE,
+                "timestamp": { "createdOn": CREATED_ON },
+                "metadata": { "nonce": NONCE },
+                "history": [{ "expiresOn": EXPIRES_ON }]
+            }),
+            json
+        );
+    }
+
+    #[test]
+    pub fn mask_json_inferred_type_schema_nullable() {
+        let mut json = get_mixed_json();
+        json["timestamp"] = Value::Null;
+        json["metadata"] = Value::Null;
+
+        get_masker(INFERRED_TYPE_SCHEMA).mask(&mut json).unwrap();
+
+        assert!(json["timestamp"].is_null());
+        assert!(json["metadata"].is_null());
+    }
+
+    #[test]
+    pub fn inferred_type_root_is_valid_schema() {
+        let mut schema: Value = serde_json::from_str(INFERRED_TYPE_SCHEMA).unwrap();
+        schema["type"] = json!(["object", "null"]);
+        assert!(ValidJsonSchema::new(schema.clone()).is_ok());
+
+        schema.as_object_mut().unwrap().remove("type");
+        assert!(ValidJsonSchema::new(schema).is_ok());
+    }
+
+    #[test]
+    pub fn mask_json_composed_root_schema() {
+        let metadata = json!({
+            "type": "object",
+            "properties": { "nonce": { "type": "integer" } }
+        });
+        let schemas = [
+            json!({ "$ref": "#/definitions/Metadata", "definitions": { "Metadata": metadata } }),
+            json!({ "allOf": [metadata] }),
+            json!({ "anyOf": [metadata] }),
+            json!({ "oneOf": [metadata] }),
+        ];
+
+        for schema in schemas {
+            let mask = Mask::try_from(&ValidJsonSchema::new(schema).unwrap()).unwrap();
+            let mut json = get_mixed_json();
+
+            JsonMasker::new(mask).mask(&mut json).unwrap();
+
+            assert_eq!(json!({ "nonce": NONCE }), json);
+        }
+    }
+
+    #[test]
+    pub fn mask_json_array_root_schema_filtered() {
+        let mut json = json!([get_mixed_json(), get_foobar_json(), get_metadata_json()]);
+
+        get_masker(ARRAY_ROOT_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(json!([{ "nonce": NONCE }, {}, get_metadata_json()]), json);
+    }
+
+    #[test]
+    pub fn mask_json_array_root_schema_type_mismatch() {
+        let mut json = get_mixed_json();
+
+        get_masker(ARRAY_ROOT_SCHEMA).mask(&mut json).unwrap();
+        assert_eq!(get_mixed_json(), json);
+
+        let error = get_masker(ARRAY_ROOT_SCHEMA)
+            .with_strict_root_type(true)
+            .mask(&mut json)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            MaskError::TypeMismatch { pointer, expected, found }
+                if pointer.is_empty() && expected == "array" && found == "object"
+        ));
+    }
+
+    #[test]
+    pub fn mask_json_scalar_root_schema() {
+        let masker = get_masker(SCALAR_ROOT_SCHEMA).with_strict_root_type(true);
+        let mut json = json!(NONCE);
+
+        masker.mask(&mut json).unwrap();
+
+        assert_eq!(json!(NONCE), json);
+        assert!(masker.mask(&mut json!(FOO)).is_err());
+        assert!(masker.mask(&mut json!(1.5)).is_err());
+    }
+
+    #[test]
+    pub fn mask_json_dependent_schema_triggered() {
+        for schema in [DEPENDENT_SCHEMAS_SCHEMA, DEPENDENCIES_SCHEMA] {
+            let mut json = json!({
+                "nonce": NONCE,
+                "billing": { "account": FOO },
+                "billingContact": { "name": FOO, "bar": BAR }
+            });
+
+            get_masker(schema).mask(&mut json).unwrap();
+
+            assert_eq!(json!({ "name": FOO }), json["billingContact"]);
+            assert_eq!(json!({ "account": FOO }), json["billing"]);
+        }
+    }
+
+    #[test]
+    pub fn mask_json_dependent_schema_not_triggered() {
+        for schema in [DEPENDENT_SCHEMAS_SCHEMA, DEPENDENCIES_SCHEMA] {
+            let mut json = json!({
+                "nonce": NONCE,
+                "billingContact": { "name": FOO }
+            });
+
+            get_masker(schema).mask(&mut json).unwrap();
+
+            assert_eq!(json!({ "nonce": NONCE }), json);
+        }
+    }
+
+    #[test]
+    pub fn mask_json_unevaluated_properties_schema() {
+        let mut json = get_metadata_json();
+        json["kind"] = json!("vm");
+        json["foo"] = json!(FOO);
+        json["labels"] = get_foobar_json();
+        json["timestamp"] = json!({ "createdOn": CREATED_ON, "bar": BAR });
+
+        get_masker(UNEVALUATED_PROPERTIES_SCHEMA)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(NONCE, json["nonce"].as_u64().unwrap());
+        // Evaluated by the then branch, so it isn't subject to unevaluatedProperties.
+        assert_eq!(
+            VM_ID,
+            Uuid::from_str(json["vmId"].as_str().unwrap()).unwrap()
+        );
+        assert_eq!(json!({ "createdOn": CREATED_ON }), json["timestamp"]);
+        assert_eq!(json!({ "foo": FOO }), json["labels"]);
+        assert_eq!(FOO, json["foo"].as_str().unwrap());
+    }
+
+    #[test]
+    pub fn mask_json_unevaluated_properties_false() {
+        let mut schema: Value = serde_json::from_str(UNEVALUATED_PROPERTIES_SCHEMA).unwrap();
+        schema["unevaluatedProperties"] = json!(false);
+        let masker =
+            JsonMasker::new(Mask::try_from(&ValidJsonSchema::new(schema).unwrap()).unwrap());
+
+        let mut json = get_metadata_json();
+        json["kind"] = json!("disk");
+        json["foo"] = json!(FOO);
+
+        masker.mask(&mut json).unwrap();
+
+        assert_eq!(json!({ "nonce": NONCE, "kind": "disk" }), json);
+    }
+
+    #[test]
+    pub fn mask_json_discriminator_schema_mapped() {
+        let mut json = json!({
+            "resources": [
+                { "kind": "vm", "vmId": VM_ID, "size": 1, "foo": FOO },
+                { "kind": "disk", "vmId": VM_ID, "size": 1, "foo": FOO },
+                { "kind": "Nic", "vmId": VM_ID, "mac": FOO, "foo": FOO }
+            ]
+        });
+
+        get_masker(DISCRIMINATOR_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(
+            json!([
+                { "kind": "vm", "vmId": VM_ID },
+                { "kind": "disk", "size": 1 },
+                { "kind": "Nic", "mac": FOO }
+            ]),
+            json["resources"]
+        );
+    }
+
+    #[test]
+    pub fn mask_json_discriminator_schema_unknown_value() {
+        let get_json = || {
+            json!({
+                "resources": [
+                    { "kind": "vm", "vmId": VM_ID },
+                    { "kind": "potato", "vmId": VM_ID, "size": 1 }
+                ]
+            })
+        };
+        let masker = |unknown_discriminator| {
+            get_masker(DISCRIMINATOR_SCHEMA).with_unknown_discriminator(unknown_discriminator)
+        };
+
+        let mut json = get_json();
+        masker(UnknownDiscriminator::KeepBase)
+            .mask(&mut json)
+            .unwrap();
+        assert_eq!(json!({ "kind": "potato" }), json["resources"][1]);
+
+        let mut json = get_json();
+        masker(UnknownDiscriminator::Drop).mask(&mut json).unwrap();
+        assert_eq!(json!([{ "kind": "vm", "vmId": VM_ID }]), json["resources"]);
+
+        let error = masker(UnknownDiscriminator::Error)
+            .mask(&mut get_json())
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            MaskError::UnknownDiscriminator { pointer, property, value }
+                if pointer == "/resources/1" && property == "kind" && value == "potato"
+        ));
+    }
+
+    #[test]
+    pub fn mask_json_discriminator_schema_typeless_unknown_value() {
+        let mut schema: Value = serde_json::from_str(DISCRIMINATOR_SCHEMA).unwrap();
+        let items = schema["properties"]["resources"]["items"]
+            .as_object_mut()
+            .unwrap();
+        items.remove("type");
+        items.remove("properties");
+        let masker =
+            JsonMasker::new(Mask::try_from(&ValidJsonSchema::new(schema).unwrap()).unwrap());
+
+        let mut json = json!({
+            "resources": [
+                { "kind": "vm", "vmId": VM_ID, "foo": FOO },
+                { "kind": "potato", "vmId": VM_ID, "foo": FOO }
+            ]
+        });
+
+        masker.mask(&mut json).unwrap();
+
+        assert_eq!(json!([{ "vmId": VM_ID }, {}]), json["resources"]);
+    }
+
+    fn get_account_json() -> Value {
+        json!({
+            "vmId": VM_ID,
+            "nonce": NONCE,
+            "password": FOO,
+            "keys": [{ "id": BAR, "secret": FOO }]
+        })
+    }
+
+    #[test]
+    pub fn mask_json_read_write_only_schema_response() {
+        let mut json = get_account_json();
+
+        get_masker(READ_WRITE_ONLY_SCHEMA)
+            .with_direction(Direction::Response)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(
+            json!({ "vmId": VM_ID, "nonce": NONCE, "keys": [{ "id": BAR }] }),
+            json
+        );
+    }
+
+    #[test]
+    pub fn mask_json_read_write_only_schema_request() {
+        let mut json = get_account_json();
+
+        get_masker(READ_WRITE_ONLY_SCHEMA)
+            .with_direction(Direction::Request)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(
+            json!({ "password": FOO, "keys": [{ "secret": FOO }] }),
+            json
+        );
+
+        let mut json = get_account_json();
+        get_masker(READ_WRITE_ONLY_SCHEMA).mask(&mut json).unwrap();
+        assert_eq!(get_account_json(), json);
+    }
+
+    #[test]
+    pub fn mask_json_read_write_only_schema_strict_request() {
+        let error = get_masker(READ_WRITE_ONLY_SCHEMA)
+            .with_direction(Direction::StrictRequest)
+            .mask(&mut json!({ "password": FOO, "keys": [{ "id": BAR }] }))
+            .unwrap_err();
+
+        assert!(matches!(error, MaskError::ReadOnly { pointer } if pointer == "/keys/0/id"));
+    }
+
+    #[test]
+    pub fn mask_json_read_write_only_reference_schema() {
+        let mut json = json!({ "id": FOO, "secret": BAR, "name": FOO });
+
+        get_masker(READ_WRITE_ONLY_REFERENCE_SCHEMA)
+            .with_direction(Direction::Request)
+            .mask(&mut json)
+            .unwrap();
+        assert_eq!(json!({ "secret": BAR, "name": FOO }), json);
+
+        let mut json = json!({ "id": FOO, "secret": BAR, "name": FOO });
+        get_masker(READ_WRITE_ONLY_REFERENCE_SCHEMA)
+            .with_direction(Direction::Response)
+            .mask(&mut json)
+            .unwrap();
+        assert_eq!(json!({ "id": FOO, "name": FOO }), json);
+
+        let error = get_masker(READ_WRITE_ONLY_REFERENCE_SCHEMA)
+            .with_direction(Direction::StrictRequest)
+            .mask(&mut json!({ "id": FOO }))
+            .unwrap_err();
+        assert!(matches!(error, MaskError::ReadOnly { pointer } if pointer == "/id"));
+    }
+
+    fn get_deprecated_json() -> Value {
+        json!({
+            "nonce": NONCE,
+            "vmId": VM_ID,
+            "timestamp": { "createdOn": CREATED_ON, "expiresOn": EXPIRES_ON },
+            "history": [{ "createdOn": CREATED_ON }, { "expiresOn": EXPIRES_ON }]
+        })
+    }
+
+    #[test]
+    pub fn mask_json_deprecated_schema_excluded() {
+        let schema = get_valid_schema(DEPRECATED_SCHEMA).unwrap();
+        let options = MaskOptions::default().with_exclude_deprecated(true);
+        let mut json = get_deprecated_json();
+
+        JsonMasker::new(Mask::from_schema(&schema, &options).unwrap())
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(
+            json!({
+                "vmId": VM_ID,
+                "timestamp": { "createdOn": CREATED_ON },
+                "history": [{ "createdOn": CREATED_ON }, {}]
+            }),
+            json
+        );
+    }
+
+    #[test]
+    pub fn mask_json_deprecated_schema_reported() {
+        let mut json = get_deprecated_json();
+
+        let report = get_masker(DEPRECATED_SCHEMA)
+            .with_report_deprecated(true)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(get_deprecated_json(), json);
+        assert_eq!(
+            vec!["/history/1/expiresOn", "/nonce", "/timestamp/expiresOn"],
+            report.deprecated
+        );
+
+        let report = get_masker(DEPRECATED_SCHEMA).mask(&mut json).unwrap();
+        assert!(report.deprecated.is_empty());
+    }
+
+    #[test]
+    pub fn mask_json_deprecated_schema_reference_siblings() {
+        let options = MaskOptions::default().with_exclude_deprecated(true);
+        let json = json!({ "vmId": VM_ID, "owner": FOO });
+
+        for (draft, excluded) in [
+            ("https://json-schema.org/draft/2019-09/schema", true),
+            ("http://json-schema.org/draft-07/schema", false),
+        ] {
+            let schema =
+                DEPRECATED_SCHEMA.replace("https://json-schema.org/draft/2019-09/schema", draft);
+
+            let mut excluded_json = json.clone();
+            JsonMasker::new(from_str_with_options(&schema, &options).unwrap())
+                .mask(&mut excluded_json)
+                .unwrap();
+
+            let mut reported_json = json.clone();
+            let report = get_masker(&schema)
+                .with_report_deprecated(true)
+                .mask(&mut reported_json)
+                .unwrap();
+
+            assert_eq!(excluded, excluded_json.get("owner").is_none());
+            assert_eq!(excluded, report.deprecated == vec!["/owner"]);
+        }
+    }
+
+    fn get_status_json() -> Value {
+        json!({
+            "status": "Hibernated",
+            "power": "Off",
+            "nonce": NONCE,
+            "vms": [{ "status": "Running" }, { "status": "Hibernated" }]
+        })
+    }
+
+    #[test]
+    pub fn mask_json_enum_schema_kept_by_default() {
+        let mut json = get_status_json();
+
+        get_masker(ENUM_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(get_status_json(), json);
+    }
+
+    #[test]
+    pub fn mask_json_enum_schema_dropped_or_replaced() {
+        let mut json = get_status_json();
+
+        get_masker(ENUM_SCHEMA)
+            .with_enum_mismatch(EnumMismatch::Drop)
+            .with_field_enum_mismatch(
+                "/definitions/Status",
+                EnumMismatch::Replace(json!("Stopped")),
+            )
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(
+            json!({
+                "status": "Stopped",
+                "nonce": NONCE,
+                "vms": [{ "status": "Running" }, { "status": "Stopped" }]
+            }),
+            json
+        );
+    }
+
+    #[test]
+    pub fn mask_json_enum_schema_numbers_compared_by_value() {
+        let mut json = json!({ "nonce": 12345.0 });
+
+        get_masker(ENUM_SCHEMA)
+            .with_enum_mismatch(EnumMismatch::Error)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(json!({ "nonce": 12345.0 }), json);
+    }
+
+    #[test]
+    pub fn mask_json_enum_schema_narrowed_by_all_of() {
+        let masker = get_masker(ENUM_SCHEMA).with_enum_mismatch(EnumMismatch::Drop);
+        let mut allowed = json!({ "state": "Running", "mode": "Manual" });
+        let mut narrowed = json!({ "state": "Stopped", "mode": "Auto" });
+
+        masker.mask(&mut allowed).unwrap();
+        masker.mask(&mut narrowed).unwrap();
+
+        assert_eq!(json!({ "state": "Running", "mode": "Manual" }), allowed);
+        assert_eq!(json!({ "mode": "Auto" }), narrowed);
+    }
+
+    #[test]
+    pub fn mask_json_enum_schema_error() {
+        let error = get_masker(ENUM_SCHEMA)
+            .with_enum_mismatch(EnumMismatch::Error)
+            .with_field_enum_mismatch("/properties/power", EnumMismatch::Keep)
+            .with_field_enum_mismatch("/definitions/Status", EnumMismatch::Keep)
+            .mask(&mut json!({ "power": "On", "nonce": 1 }))
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            MaskError::EnumMismatch { pointer, value } if pointer == "/nonce" && value == 1
+        ));
+    }
+
+    #[test]
+    pub fn mask_json_default_schema_not_filled_by_default() {
+        let mut json = json!({ "nonce": NONCE });
+
+        get_masker(DEFAULT_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(json!({ "nonce": NONCE }), json);
+    }
+
+    #[test]
+    pub fn mask_json_default_schema_filled() {
+        let mut json = json!({ "nonce": NONCE, "vms": [{}, { "size": "Large" }] });
+
+        get_masker(DEFAULT_SCHEMA)
+            .with_fill_defaults(true)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(
+            json!({
+                "nonce": NONCE,
+                "status": "Running",
+                "timestamp": { "createdOn": CREATED_ON, "expiresOn": EXPIRES_ON },
+                "settings": { "retries": 3 },
+                "vms": [{ "size": "Small" }, { "size": "Large" }]
+            }),
+            json
+        );
+    }
+
+    #[test]
+    pub fn mask_json_default_schema_required_by_all_of() {
+        let mut schema: Value = serde_json::from_str(DEFAULT_SCHEMA).unwrap();
+        schema["required"] = json!(["nonce"]);
+        schema["allOf"] = json!([{ "required": ["settings"] }]);
+        let masker =
+            JsonMasker::new(Mask::try_from(&ValidJsonSchema::new(schema).unwrap()).unwrap())
+                .with_fill_defaults(true);
+
+        let mut json = json!({ "nonce": NONCE });
+        masker.mask(&mut json).unwrap();
+
+        assert_eq!(json!({ "retries": 3 }), json["settings"]);
+    }
+
+    #[test]
+    pub fn mask_options_draft() {
+        let schema = serde_json::from_str::<Value>(SIMPLE_SCHEMA).unwrap();
+        let mut json = get_foobar_json();
+
+        let options = MaskOptions::default().with_draft(Draft::Draft4);
+        let mask = from_str_with_options(SIMPLE_SCHEMA, &options).unwrap();
+        JsonMasker::new(mask).mask(&mut json).unwrap();
+
+        assert_eq!(json!({}), json);
+        assert!(ValidJsonSchema::with_options(
+            schema.clone(),
+            &MaskOptions::default().with_draft(Draft::Draft7)
+        )
+        .is_err());
+
+        let mut unversioned = schema;
+        unversioned.as_object_mut().unwrap().remove("$schema");
+        assert!(
+            ValidJsonSchema::with_options(unversioned.clone(), &MaskOptions::default()).is_ok()
+        );
+        assert!(ValidJsonSchema::with_options(
+            unversioned,
+            &MaskOptions::default().with_require_schema_keyword(true)
+        )
+        .is_err());
+    }
+
+    #[test]
+    pub fn mask_options_unknown_keywords() {
+        let strict = MaskOptions::default().with_unknown_keywords(UnknownKeywords::Strict);
+
+        assert!(from_str_with_options(SIMPLE_SCHEMA, &strict).is_ok());
+        assert!(from_str_with_options(UNEVALUATED_PROPERTIES_SCHEMA, &strict).is_ok());
+        assert!(from_str(UNKNOWN_KEYWORD_SCHEMA).is_ok());
+
+        let Err(error) = from_str_with_options(UNKNOWN_KEYWORD_SCHEMA, &strict) else {
+            panic!("the unknown keyword wasn't rejected");
+        };
+        assert!(error.to_string().contains("/properties/foo"));
+        assert!(matches!(
+            error,
+            ParseError::InvalidJsonSchema(message) if message.contains("/properties/foo")
+        ));
+    }
+
+    #[test]
+    pub fn mask_options_validate_formats() {
+        let mut json = json!({ "contact": "not an email" });
+
+        let mask = from_str_with_options(FORMAT_SCHEMA, &MaskOptions::default()).unwrap();
+        JsonMasker::new(mask)
+            .with_branch_selection(BranchSelection::Matching)
+            .with_unmatched_branches(UnmatchedBranches::Drop)
+            .mask(&mut json)
+            .unwrap();
+        assert_eq!(Value::Null, json);
+
+        let mut json = json!({ "contact": "not an email" });
+        let options = MaskOptions::default().with_validate_formats(false);
+        let mask = from_str_with_options(FORMAT_SCHEMA, &options).unwrap();
+        JsonMasker::new(mask)
+            .with_branch_selection(BranchSelection::Matching)
+            .with_unmatched_branches(UnmatchedBranches::Drop)
+            .mask(&mut json)
+            .unwrap();
+        assert_eq!(json!({ "contact": "not an email" }), json);
+    }
+
+    #[test]
+    pub fn mask_json_coerce_types() {
+        let mut json = json!({
+            "nonce": NONCE,
+            "vmId": VM_ID,
+            "foo2": true,
+            "count": "42",
+            "ratio": "0.5",
+            "enabled": "true",
+            "size": 3.0
+        });
+
+        let report = get_masker(COERCION_SCHEMA)
+            .with_coerce_types(true)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(
+            json!({
+                "nonce": NONCE.to_string(),
+                "vmId": VM_ID,
+                "foo2": "true",
+                "count": 42,
+                "ratio": 0.5,
+                "enabled": true,
+                "size": 3
+            }),
+            json
+        );
+        assert!(report.unconverted.is_empty());
+    }
+
+    #[test]
+    pub fn mask_json_coerce_types_unconverted() {
+        let mut json = json!({ "count": "many", "enabled": "yes", "size": 1e30 });
+
+        let report = get_masker(COERCION_SCHEMA)
+            .with_coerce_types(true)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(
+            json!({ "count": "many", "enabled": "yes", "size": 1e30 }),
+            json
+        );
+        assert_eq!(vec!["/count", "/enabled", "/size"], report.unconverted);
+    }
+
+    #[test]
+    pub fn mask_json_coerce_types_disabled() {
+        let mut json = json!({ "nonce": NONCE, "count": "42" });
+
+        let report = get_masker(COERCION_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(json!({ "nonce": NONCE, "count": "42" }), json);
+        assert!(report.unconverted.is_empty());
+    }
+
+    #[test]
+    pub fn mask_json_type_mismatch_kept_by_default() {
+        let mut json = json!({ "nonce": NONCE, "timestamp": CREATED_ON });
+
+        get_masker(NESTED_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(json!({ "nonce": NONCE, "timestamp": CREATED_ON }), json);
+    }
+
+    #[test]
+    pub fn mask_json_type_mismatch_dropped() {
+        let mut json = json!({
+            "vms": [{ "nonce": NONCE, "vmId": VM_ID }, "vm", { "vmId": VM_ID.to_string() }],
+            "tags": ["foo", { "bar": BAR }, "baz"]
+        });
+
+        get_masker(ARRAY_SCHEMA)
+            .with_type_mismatch(TypeMismatch::Drop)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(
+            json!({
+                "vms": [{ "vmId": VM_ID }, { "vmId": VM_ID }],
+                "tags": ["foo", "baz"]
+            }),
+            json
+        );
+    }
+
+    #[test]
+    pub fn mask_json_type_mismatch_error() {
+        let mut json = json!({ "nonce": NONCE.to_string(), "timestamp": [CREATED_ON] });
+
+        let error = get_masker(NESTED_SCHEMA)
+            .with_type_mismatch(TypeMismatch::Error)
+            .mask(&mut json)
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            MaskError::TypeMismatch { pointer, expected, found }
+                if pointer == "/timestamp" && expected == "object" && found == "array"
+        ));
+    }
+
+    #[test]
+    pub fn mask_json_rename_schema() {
+        let mut json = json!({
+            "nonce": NONCE,
+            "vmId": VM_ID,
+            "timestamp": { "created": CREATED_ON, "expiresOn": EXPIRES_ON }
+        });
+
+        get_masker(RENAME_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(
+            json!({
+                "nonce": NONCE,
+                "virtualMachineId": VM_ID,
+                "timestamp": { "createdOn": CREATED_ON, "expiresOn": EXPIRES_ON }
+            }),
+            json
+        );
+    }
+
+    #[test]
+    pub fn mask_json_rename_schema_precedence() {
+        let mut json = json!({
+            "vm_id": "alias",
+            "vmId": VM_ID,
+            "timestamp": { "createdOn": CREATED_ON, "created": EXPIRES_ON }
+        });
+
+        get_masker(RENAME_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(
+            json!({
+                "virtualMachineId": VM_ID,
+                "timestamp": { "createdOn": CREATED_ON }
+            }),
+            json
+        );
+    }
+
+    #[test]
+    pub fn mask_json_rename_schema_conflict() {
+        assert!(matches!(
+            from_str(INVALID_SCHEMA_RENAME_CONFLICT),
+            Err(ParseError::RenameConflict { pointer, name })
+                if pointer == "/properties" && name == "vmId"
+        ));
+        assert!(matches!(
+            from_str(INVALID_SCHEMA_RENAME_CONFLICT_ALL_OF),
+            Err(ParseError::RenameConflict { pointer, name })
+                if pointer == "/allOf/1/properties" && name == "old"
+        ));
+    }
+
+    #[test]
+    pub fn mask_json_rename_schema_repeated_alias() {
+        let mut json = json!({ "old": FOO });
+
+        get_masker(RENAME_SCHEMA_REPEATED_ALIAS)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(json!({ "new": FOO }), json);
+    }
+
+    #[test]
+    pub fn mask_json_move_schema() {
+        let mut json = json!({
+            "nonce": NONCE,
+            "timestamp": { "createdOn": CREATED_ON, "expiresOn": EXPIRES_ON }
+        });
+
+        get_masker(MOVE_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(
+            json!({
+                "nonce": NONCE,
+                "createdOn": CREATED_ON,
+                "expiresOn": EXPIRES_ON,
+                "metadata": { "nonce": NONCE }
+            }),
+            json
+        );
+    }
+
+    #[test]
+    pub fn mask_json_move_schema_missing_source() {
+        let mut json = json!({ "createdOn": CREATED_ON, "timestamp": {} });
+
+        get_masker(MOVE_SCHEMA).mask(&mut json).unwrap();
+
+        assert_eq!(json!({ "createdOn": CREATED_ON, "metadata": {} }), json);
+    }
+
+    #[test]
+    pub fn mask_json_enum_map_schema() {
+        let mut json = json!({
+            "status": "Hibernated",
+            "power": "Cycling",
+            "vms": [{ "status": "Deallocated" }, { "status": "Deleting" }]
+        });
+
+        get_masker(ENUM_MAP_SCHEMA)
+            .with_enum_mismatch(EnumMismatch::Error)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(
+            json!({
+                "status": "Stopped",
+                "power": "Off",
+                "vms": [{ "status": "Stopped" }, { "status": "Unknown" }]
+            }),
+            json
+        );
+    }
+
+    #[test]
+    pub fn mask_json_enum_map_schema_without_fallback() {
+        let error = get_masker(ENUM_MAP_SCHEMA)
+            .with_enum_mismatch(EnumMismatch::Error)
+            .mask(&mut json!({ "power": "Surging" }))
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            MaskError::EnumMismatch { pointer, value } if pointer == "/power" && value == "Surging"
+        ));
+    }
+
+    #[test]
+    pub fn mask_json_validate_output() {
+        let mut json = json!({ "nonce": NONCE.to_string(), "vmId": VM_ID, "foo": FOO });
+
+        get_masker(SIMPLE_SCHEMA)
+            .with_validate_output(true)
+            .mask(&mut json)
+            .unwrap();
+
+        assert_eq!(json!({ "nonce": NONCE.to_string(), "vmId": VM_ID }), json);
+    }
+
+    #[test]
+    pub fn mask_json_validate_output_errors() {
+        let mut json = json!({ "nonce": NONCE, "vmId": VM_ID, "timestamp": { "createdOn": 1 } });
+
+        let error = get_masker(NESTED_SCHEMA)
+            .with_validate_output(true)
+            .mask(&mut json)
+            .unwrap_err();
+
+        let MaskError::InvalidOutput(mut errors) = error else {
+            panic!("expected invalid output, found {error:?}");
+        };
+        errors.sort_by(|a, b| a.pointer.cmp(&b.pointer));
+
+        assert_eq!(
+            vec![("/nonce", "type"), ("/timestamp/createdOn", "type")],
+            errors
+                .iter()
+                .map(|error| (error.pointer.as_str(), error.keyword.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert!(errors[0].message.contains("is not of type"));
+    }
+
+    const SIMPLE_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-04/schema",
+    "title": "Simple Schema",
+    "description": "Arbitrary object for testing",
+    "type": "object",
+    "properties": {
+        "nonce": {
+            "type": "string"
+        },
+        "vmId": {
+            "type": "string"
+        },
+        "foo2": {
+            "type": "string"
+        }
+    }
+}
+"#;
+
+    const NESTED_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-04/schema",
+    "title": "Simple Schema",
+    "description": "Arbitrary nested object for testing",
+    "type": "object",
+    "properties": {
+        "nonce": {
+            "type": "string"
+        },
+        "vmId": {
+            "type": "string"
+        },
+        "timestamp": {
+            "type": "object",
+            "properties": {
+                "createdOn": {
+                    "type": "string"
+                },
+                "expiresOn": {
+                    "type": "string"
+                }
+            }
+        },
+        "foo5": {
+            "type": "string"
+        }
+    }
+}
+"#;
+
+    const ARRAY_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-04/schema",
+    "title": "Array Schema",
+    "description": "Arbitrary object with arrays for testing",
+    "type": "object",
+    "properties": {
+        "vms": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "nonce": {
+                        "type": "string"
+                    },
+                    "vmId": {
+                        "type": "string"
+                    }
+                }
+            }
+        },
+        "grid": {
+            "type": "array",
+            "items": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "nonce": {
+                            "type": "string"
+                        },
+                        "vmId": {
+                            "type": "string"
+                        }
+                    }
+                }
+            }
+        },
+        "tags": {
+            "type": "array",
+            "items": {
+                "type": "string"
+            }
+        }
+    }
+}
+"#;
+
+    const TUPLE_SCHEMA_PREFIX_ITEMS: &str = r#"
+{
+    "$schema": "https://json-schema.org/draft/2020-12/schema",
+    "title": "Tuple Schema",
+    "description": "Arbitrary object with a tuple for testing",
+    "type": "object",
+    "properties": {
+        "events": {
+            "type": "array",
+            "prefixItems": [
+                {
+                    "type": "string"
+                },
+                {
+                    "type": "object",
+                    "properties": {
+                        "nonce": {
+                            "type": "integer"
+                        }
+                    }
+                }
+            ],
+            "items": {
+                "type": "object",
+                "properties": {
+                    "foo": {
+                        "type": "string"
+                    }
+                }
+            }
+        }
+    }
+}
+"#;
+
+    const TUPLE_SCHEMA_ARRAY_ITEMS: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-04/schema",
+    "title": "Tuple Schema",
+    "description": "Arbitrary object with a tuple for testing",
+    "type": "object",
+    "properties": {
+        "events": {
+            "type": "array",
+            "items": [
+                {
+                    "type": "string"
+                },
+                {
+                    "type": "object",
+                    "properties": {
+                        "nonce": {
+                            "type": "integer"
+                        }
+                    }
+                }
+            ],
+            "additionalItems": {
+                "type": "object",
+                "properties": {
+                    "foo": {
+                        "type": "string"
+                    }
+                }
+            }
+        }
+    }
+}
+"#;
+
+    const TUPLE_SCHEMA_CLOSED_PREFIX_ITEMS: &str = r#"
+{
+    "$schema": "https://json-schema.org/draft/2020-12/schema",
+    "title": "Tuple Schema",
+    "description": "Arbitrary object with a tuple that allows no extra items for testing",
+    "type": "object",
+    "properties": {
+        "events": {
+            "type": "array",
+            "prefixItems": [
+                {
+                    "type": "string"
+                }
+            ],
+            "items": false
+        }
+    }
+}
+"#;
+
+    const TUPLE_SCHEMA_CLOSED_ARRAY_ITEMS: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-04/schema",
+    "title": "Tuple Schema",
+    "description": "Arbitrary object with a tuple that allows no extra items for testing",
+    "type": "object",
+    "properties": {
+        "events": {
+            "type": "array",
+            "items": [
+                {
+                    "type": "string"
+                }
+            ],
+            "additionalItems": false
+        }
+    }
+}
+"#;
+
+    const REFERENCE_SCHEMA_DEFINITIONS: &str = r##"
+{
+    "$schema": "http://json-schema.org/draft-04/schema",
+    "title": "Reference Schema",
+    "description": "Arbitrary object with references for testing",
+    "type": "object",
+    "definitions": {
+        "Nonce": {
+            "type": "integer"
+        },
+        "Timestamp": {
+            "type": "object",
+            "properties": {
+                "createdOn": {
+                    "type": "string"
+                },
+                "expiresOn": {
+                    "type": "string"
+                }
+            }
+        }
+    },
+    "properties": {
+        "nonce": {
+            "$ref": "#/definitions/Nonce"
+        },
+        "timestamp": {
+            "$ref": "#/definitions/Timestamp"
+        },
+        "history": {
+            "type": "array",
+            "items": {
+                "$ref": "#/definitions/Timestamp"
+            }
+        }
+    }
+}
+"##;
+
+    const REFERENCE_SCHEMA_DEFS: &str = r##"
+{
+    "$schema": "https://json-schema.org/draft/2020-12/schema",
+    "title": "Reference Schema",
+    "description": "Arbitrary object with references for testing",
+    "type": "object",
+    "$defs": {
+        "Timestamp": {
+            "type": "object",
+            "properties": {
+                "createdOn": {
+                    "type": "string"
+                },
+                "expiresOn": {
+                    "type": "string"
+                }
+            }
+        },
+        "History": {
+            "type": "array",
+            "items": {
+                "$ref": "#/$defs/Timestamp"
+            }
+        }
+    },
+    "properties": {
+        "nonce": {
+            "type": "integer"
+        },
+        "timestamp": {
+            "$ref": "#/$defs/Timestamp"
+        },
+        "history": {
+            "$ref": "#/$defs/History"
+        }
+    }
+}
+"##;
+
+    const REFERENCE_SCHEMA_URIS: &str = r##"
+{
+    "$schema": "https://json-schema.org/draft/2020-12/schema",
+    "$id": "http://example.com/s.json",
+    "title": "Reference Schema",
+    "description": "Arbitrary object with references in every form for testing",
+    "type": "object",
+    "$defs": {
+        "Created On": {
+            "type": "object",
+            "properties": {
+                "createdOn": {
+                    "type": "string"
+                }
+            }
+        },
+        "ExpiresOn": {
+            "$anchor": "expiry",
+            "type": "object",
+            "properties": {
+                "expiresOn": {
+                    "type": "string"
+                }
+            }
+        }
+    },
+    "properties": {
+        "nonce": {
+            "$ref": "http://example.com/s.json#/properties/timestamp/properties/nonce"
+        },
+        "timestamp": {
+            "$ref": "#/$defs/Created%20On",
+            "properties": {
+                "nonce": {
+                    "type": "integer"
+                }
+            }
+        },
+        "history": {
+            "type": "array",
+            "items": {
+                "$ref": "#expiry"
+            }
+        },
+        "remote": {
+            "$ref": "http://example.com/remote.json"
+        }
+    }
+}
+"##;
+
+    const REFERENCE_SCHEMA_SIBLINGS: &str = r##"
+{
+    "$schema": "https://json-schema.org/draft/2020-12/schema",
+    "title": "Reference Schema",
+    "description": "Arbitrary object with keywords next to a reference for testing",
+    "type": "object",
+    "$defs": {
+        "Metadata": {
+            "type": "object",
+            "properties": {
+                "nonce": {
+                    "type": "integer"
+                },
+                "vmId": {
+                    "type": "string"
+                }
+            }
+        }
+    },
+    "properties": {
+        "vm": {
+            "$ref": "#/$defs/Metadata",
+            "properties": {
+                "extra": {
+                    "type": "string"
+                }
+            }
+        }
+    }
+}
+"##;
+
+    const INVALID_SCHEMA_DANGLING_REFERENCE: &str = r##"
+{
+    "$schema": "http://json-schema.org/draft-04/schema",
+    "title": "Reference Schema",
+    "description": "Arbitrary object with a dangling reference for testing",
+    "type": "object",
+    "properties": {
+        "timestamp": {
+            "$ref": "#/definitions/Missing"
+        }
+    }
+}
+"##;
+
+    const BOOLEAN_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Boolean Schema",
+    "description": "Arbitrary object with boolean schemas for testing",
+    "type": "object",
+    "properties": {
+        "allowed": true,
+        "forbidden": false,
+        "either": {
+            "type": "object",
+            "anyOf": [
+                false,
+                {
+                    "properties": {
+                        "foo": {
+                            "type": "string"
+                        }
+                    }
+                }
+            ]
+        }
+    }
+}
+"#;
+
+    const RECURSIVE_SCHEMA_DEFINITION: &str = r##"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Recursive Schema",
+    "description": "Arbitrary tree of folders for testing",
+    "type": "object",
+    "definitions": {
+        "Folder": {
+            "type": "object",
+            "properties": {
+                "name": {
+                    "type": "string"
+                },
+                "subfolders": {
+                    "type": "array",
+                    "items": {
+                        "$ref": "#/definitions/Folder"
+                    }
+                }
+            }
+        }
+    },
+    "properties": {
+        "name": {
+            "type": "string"
+        },
+        "subfolders": {
+            "type": "array",
+            "items": {
+                "$ref": "#/definitions/Folder"
+            }
+        }
+    }
+}
+"##;
+
+    const RECURSIVE_SCHEMA_ROOT: &str = r##"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Recursive Schema",
+    "description": "Arbitrary linked list for testing",
+    "type": "object",
+    "properties": {
+        "nonce": {
+            "type": "integer"
+        },
+        "vmId": {
+            "type": "string"
+        },
+        "parent": {
+            "$ref": "#"
+        }
+    }
+}
+"##;
+
+    const INVALID_SCHEMA_CIRCULAR_REFERENCE: &str = r##"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Circular Reference Schema",
+    "description": "Arbitrary object with references that never resolve for testing",
+    "type": "object",
+    "definitions": {
+        "First": {
+            "$ref": "#/definitions/Second"
+        },
+        "Second": {
+            "$ref": "#/definitions/First"
+        }
+    },
+    "properties": {
+        "nonce": {
+            "$ref": "#/definitions/First"
+        }
+    }
+}
+"##;
+
+    const ALL_OF_SCHEMA: &str = r##"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "All Of Schema",
+    "description": "Arbitrary composed object for testing",
+    "type": "object",
+    "definitions": {
+        "Base": {
+            "type": "object",
+            "properties": {
+                "nonce": {
+                    "type": "integer"
+                },
+                "timestamp": {
+                    "type": "object",
+                    "properties": {
+                        "createdOn": {
+                            "type": "string"
+                        }
+                    }
+                },
+                "child": {
+                    "$ref": "#"
+                }
+            }
+        }
+    },
+    "allOf": [
+        {
+            "$ref": "#/definitions/Base"
+        },
+        {
+            "properties": {
+                "foo": {
+                    "type": "string"
+                },
+                "timestamp": {
+                    "type": "object",
+                    "properties": {
+                        "expiresOn": {
+                            "type": "string"
+                        }
+                    }
+                }
+            }
+        }
+    ]
+}
+"##;
+
+    const ONE_OF_SCHEMA: &str = r##"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "One Of Schema",
+    "description": "Arbitrary polymorphic object for testing",
+    "type": "object",
+    "definitions": {
+        "Vm": {
+            "type": "object",
+            "required": ["vmId"],
+            "properties": {
+                "kind": {
+                    "enum": ["vm"]
+                },
+                "vmId": {
+                    "type": "string"
+                }
+            }
+        },
+        "Disk": {
+            "type": "object",
+            "required": ["size"],
+            "properties": {
+                "kind": {
+                    "enum": ["disk"]
+                },
+                "size": {
+                    "type": "integer"
+                }
+            }
+        }
+    },
+    "properties": {
+        "nonce": {
+            "type": "integer"
+        },
+        "resource": {
+            "oneOf": [
+                {
+                    "$ref": "#/definitions/Vm"
+                },
+                {
+                    "$ref": "#/definitions/Disk"
+                }
+            ]
+        }
+    }
+}
+"##;
+
+    const ANY_OF_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Any Of Schema",
+    "description": "Arbitrary array of polymorphic objects for testing",
+    "type": "object",
+    "properties": {
+        "history": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "anyOf": [
+                    {
+                        "required": ["createdOn"],
+                        "properties": {
+                            "createdOn": {
+                                "type": "string"
+                            },
+                            "foo": {
+                                "type": "string"
+                            }
+                        }
+                    },
+                    {
+                        "required": ["expiresOn"],
+                        "properties": {
+                            "expiresOn": {
+                                "type": "string"
+                            },
+                            "bar": {
+                                "type": "string"
+                            }
+                        }
+                    }
+                ]
+            }
+        }
+    }
+}
+"#;
+
+    const ESCAPED_KEY_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Escaped Key Schema",
+    "description": "Arbitrary object with keys that must be escaped in a URI for testing",
+    "type": "object",
+    "properties": {
+        "a%25": {
+            "type": "object",
+            "if": {
+                "required": ["k"]
+            },
+            "then": {
+                "properties": {
+                    "k": {}
+                }
+            }
+        },
+        "a b": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "anyOf": [
+                    {
+                        "required": ["createdOn"],
+                        "properties": {
+                            "createdOn": {
+                                "type": "string"
+                            },
+                            "foo": {
+                                "type": "string"
+                            }
+                        }
+                    },
+                    {
+                        "required": ["expiresOn"],
+                        "properties": {
+                            "expiresOn": {
+                                "type": "string"
+                            },
+                            "bar": {
+                                "type": "string"
+                            }
+                        }
+                    }
+                ]
+            }
+        }
+    }
+}
+"#;
+
+    const CONDITIONAL_SCHEMA: &str = r##"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Conditional Schema",
+    "description": "Arbitrary variant record for testing",
+    "type": "object",
+    "properties": {
+        "kind": {
+            "type": "string"
+        },
+        "nonce": {
+            "type": "integer"
+        },
+        "children": {
+            "type": "array",
+            "items": {
+                "$ref": "#"
+            }
+        }
+    },
+    "if": {
+        "required": ["kind"],
+        "properties": {
+            "kind": {
+                "const": "vm"
+            }
+        }
+    },
+    "then": {
+        "properties": {
+            "vmId": {
+                "type": "string"
+            }
+        }
+    },
+    "else": {
+        "properties": {
+            "size": {
+                "type": "integer"
+            }
+        }
+    }
+}
+"##;
+
+    const ADDITIONAL_PROPERTIES_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Additional Properties Schema",
+    "description": "Arbitrary object with maps for testing",
+    "type": "object",
+    "additionalProperties": false,
+    "properties": {
+        "nonce": {
+            "type": "integer"
+        },
+        "labels": {
+            "type": "object",
+            "additionalProperties": true
+        },
+        "timestamp": {
+            "type": "object",
+            "properties": {
+                "createdOn": {
+                    "type": "string"
+                }
+            }
+        },
+        "vms": {
+            "type": "object",
+            "additionalProperties": {
+                "type": "object",
+                "properties": {
+                    "nonce": {
+                        "type": "integer"
+                    },
+                    "vmId": {
+                        "type": "string"
+                    }
+                }
+            }
+        }
+    }
+}
+"#;
+
+    const PATTERN_PROPERTIES_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Pattern Properties Schema",
+    "description": "Arbitrary object with extension and locale keys for testing",
+    "type": "object",
+    "properties": {
+        "nonce": {
+            "type": "integer"
+        },
+        "x-ms-timestamp": {
+            "type": "object",
+            "properties": {
+                "createdOn": {
+                    "type": "string"
+                }
+            }
+        },
+        "names": {
+            "type": "object",
+            "patternProperties": {
+                "^[a-z]{2}-[A-Z]{2}$": {
+                    "type": "object",
+                    "properties": {
+                        "name": {
+                            "type": "string"
+                        }
+                    }
+                }
+            },
+            "additionalProperties": {
+                "type": "object",
+                "additionalProperties": true
+            }
+        }
+    },
+    "patternProperties": {
+        "^x-ms-": {
+            "type": "string"
+        },
+        "^x-ms-timestamp$": {
+            "type": "object",
+            "properties": {
+                "expiresOn": {
+                    "type": "string"
+                }
+            }
+        }
+    }
+}
+"#;
+
+    const INFERRED_TYPE_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Inferred Type Schema",
+    "description": "Arbitrary object with nullable and implicit types for testing",
+    "properties": {
+        "nonce": {
+            "type": "integer"
+        },
+        "timestamp": {
+            "type": ["object", "null"],
+            "properties": {
+                "createdOn": {
+                    "type": "string"
+                }
+            }
+        },
+        "metadata": {
+            "properties": {
+                "nonce": {
+                    "type": "integer"
+                }
+            }
+        },
+        "history": {
+            "items": {
+                "properties": {
+                    "expiresOn": {
+                        "type": "string"
+                    }
+                }
+            }
+        }
+    }
+}
+"#;
+
+    const ARRAY_ROOT_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Array Root Schema",
+    "description": "Arbitrary list for testing",
+    "type": "array",
+    "items": {
+        "type": "object",
+        "properties": {
+            "nonce": {
+                "type": "integer"
+            },
+            "vmId": {
+                "type": "string"
+            }
+        }
+    }
+}
+"#;
+
+    const SCALAR_ROOT_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Scalar Root Schema",
+    "description": "Arbitrary integer for testing",
+    "type": "integer"
+}
+"#;
+
+    const DEPENDENT_SCHEMAS_SCHEMA: &str = r#"
+{
+    "$schema": "https://json-schema.org/draft/2020-12/schema",
+    "title": "Dependent Schemas Schema",
+    "description": "Arbitrary object with dependent properties for testing",
+    "type": "object",
+    "properties": {
+        "nonce": {
+            "type": "integer"
+        },
+        "billing": {
+            "type": "object",
+            "properties": {
+                "account": {
+                    "type": "string"
+                }
+            }
+        }
+    },
+    "dependentSchemas": {
+        "billing": {
+            "properties": {
+                "billingContact": {
+                    "type": "object",
+                    "properties": {
+                        "name": {
+                            "type": "string"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+"#;
+
+    const DEPENDENCIES_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-04/schema",
+    "title": "Dependencies Schema",
+    "description": "Arbitrary object with dependent properties for testing",
+    "type": "object",
+    "properties": {
+        "nonce": {
+            "type": "integer"
+        },
+        "billing": {
+            "type": "object",
+            "properties": {
+                "account": {
+                    "type": "string"
+                }
+            }
+        }
+    },
+    "dependencies": {
+        "nonce": ["billing"],
+        "billing": {
+            "properties": {
+                "billingContact": {
+                    "type": "object",
+                    "properties": {
+                        "name": {
+                            "type": "string"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+"#;
+
+    const UNEVALUATED_PROPERTIES_SCHEMA: &str = r##"
+{
+    "$schema": "https://json-schema.org/draft/2020-12/schema",
+    "title": "Unevaluated Properties Schema",
+    "description": "Arbitrary composed object for testing",
+    "type": "object",
+    "$defs": {
+        "Base": {
+            "type": "object",
+            "properties": {
+                "nonce": {
+                    "type": "integer"
+                },
+                "kind": {
+                    "type": "string"
+                }
+            }
+        }
+    },
+    "allOf": [
+        {
+            "$ref": "#/$defs/Base"
+        },
+        {
+            "properties": {
+                "timestamp": {
+                    "type": "object",
+                    "properties": {
+                        "createdOn": {
+                            "type": "string"
+                        }
+                    }
+                }
+            }
+        }
+    ],
+    "if": {
+        "properties": {
+            "kind": {
+                "const": "vm"
+            }
+        }
+    },
+    "then": {
+        "properties": {
+            "vmId": {
+                "type": "string"
+            }
+        }
+    },
+    "unevaluatedProperties": {
+        "type": "object",
+        "properties": {
+            "foo": {
+                "type": "string"
+            }
+        }
+    }
+}
+"##;
+
+    const DISCRIMINATOR_SCHEMA: &str = r##"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Discriminator Schema",
+    "description": "Arbitrary list of polymorphic objects for testing",
+    "type": "object",
+    "components": {
+        "schemas": {
+            "Vm": {
+                "type": "object",
+                "properties": {
+                    "vmId": {
+                        "type": "string"
+                    }
+                }
+            },
+            "Disk": {
+                "type": "object",
+                "properties": {
+                    "size": {
+                        "type": "integer"
+                    }
+                }
+            },
+            "Nic": {
+                "type": "object",
+                "properties": {
+                    "mac": {
+                        "type": "string"
+                    }
+                }
+            }
+        }
+    },
+    "properties": {
+        "resources": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "kind": {
+                        "type": "string"
+                    }
+                },
+                "oneOf": [
+                    {
+                        "$ref": "#/components/schemas/Vm"
+                    },
+                    {
+                        "$ref": "#/components/schemas/Disk"
+                    },
+                    {
+                        "$ref": "#/components/schemas/Nic"
+                    }
+                ],
+                "discriminator": {
+                    "propertyName": "kind",
+                    "mapping": {
+                        "vm": "#/components/schemas/Vm",
+                        "disk": "Disk"
+                    }
+                }
+            }
+        }
+    }
+}
+"##;
+
+    const READ_WRITE_ONLY_SCHEMA: &str = r##"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Read Write Only Schema",
+    "description": "Arbitrary object shared by requests and responses for testing",
+    "type": "object",
+    "definitions": {
+        "ServerGenerated": {
+            "readOnly": true
+        }
+    },
+    "properties": {
+        "vmId": {
+            "type": "string",
+            "readOnly": true
+        },
+        "nonce": {
+            "type": "integer",
+            "allOf": [
+                {
+                    "$ref": "#/definitions/ServerGenerated"
+                }
+            ]
+        },
+        "password": {
+            "type": "string",
+            "writeOnly": true
+        },
+        "keys": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "id": {
+                        "type": "string",
+                        "readOnly": true
+                    },
+                    "secret": {
+                        "type": "string",
+                        "writeOnly": true
+                    }
+                }
+            }
+        }
+    }
+}
+"##;
+
+    const READ_WRITE_ONLY_REFERENCE_SCHEMA: &str = r##"
+{
+    "$schema": "https://json-schema.org/draft/2020-12/schema",
+    "title": "Read Write Only Schema",
+    "description": "Arbitrary object with read and write only references for testing",
+    "type": "object",
+    "$defs": {
+        "Id": {
+            "type": "string"
+        }
+    },
+    "properties": {
+        "id": {
+            "$ref": "#/$defs/Id",
+            "readOnly": true
+        },
+        "secret": {
+            "$ref": "#/$defs/Id",
+            "writeOnly": true
+        },
+        "name": {
+            "$ref": "#/$defs/Id"
+        }
+    }
+}
+"##;
+
+    const DEPRECATED_SCHEMA: &str = r##"
+{
+    "$schema": "https://json-schema.org/draft/2019-09/schema",
+    "title": "Deprecated Schema",
+    "description": "Arbitrary object with deprecated properties for testing",
+    "type": "object",
+    "$defs": {
+        "Name": {
+            "type": "string"
+        },
+        "Timestamp": {
+            "type": "object",
+            "properties": {
+                "createdOn": {
+                    "type": "string"
+                },
+                "expiresOn": {
+                    "$ref": "#/$defs/Expiry"
+                }
+            }
+        },
+        "Expiry": {
+            "type": "string",
+            "deprecated": true
+        }
+    },
+    "properties": {
+        "nonce": {
+            "type": "integer",
+            "deprecated": true
+        },
+        "vmId": {
+            "type": "string"
+        },
+        "owner": {
+            "$ref": "#/$defs/Name",
+            "deprecated": true
+        },
+        "timestamp": {
+            "$ref": "#/$defs/Timestamp"
+        },
+        "history": {
+            "type": "array",
+            "items": {
+                "$ref": "#/$defs/Timestamp"
+            }
+        }
+    }
+}
+"##;
+
+    const ENUM_SCHEMA: &str = r##"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Enum Schema",
+    "description": "Arbitrary object with enums for testing",
+    "type": "object",
+    "definitions": {
+        "Status": {
+            "type": "string",
+            "enum": ["Running", "Stopped"]
+        }
+    },
+    "properties": {
+        "status": {
+            "$ref": "#/definitions/Status"
+        },
+        "power": {
+            "enum": ["On"]
+        },
+        "state": {
+            "allOf": [
+                {
+                    "$ref": "#/definitions/Status"
+                },
+                {
+                    "enum": ["Running"]
+                }
+            ]
+        },
+        "mode": {
+            "anyOf": [
+                {
+                    "enum": ["Auto"]
+                },
+                {
+                    "enum": ["Manual"]
+                }
+            ]
+        },
+        "nonce": {
+            "const": 12345
+        },
+        "vms": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "status": {
+                        "$ref": "#/definitions/Status"
+                    }
+                }
+            }
+        }
+    }
+}
+"##;
+
+    const DEFAULT_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Default Schema",
+    "description": "Arbitrary object with defaults for testing",
+    "type": "object",
+    "required": ["nonce", "settings"],
+    "properties": {
+        "nonce": {
+            "type": "integer"
+        },
+        "status": {
+            "type": "string",
+            "default": "Running"
         },
         "timestamp": {
             "type": "object",
+            "default": { "createdOn": "2023-07-28 17:59:14Z", "expiresOn": "2023-07-28 20:59:14Z" },
             "properties": {
                 "createdOn": {
                     "type": "string"
@@ -326,13 +5290,302 @@ mod tests {
                 }
             }
         },
-        "foo5": {
+        "settings": {
+            "type": "object",
+            "properties": {
+                "retries": {
+                    "type": "integer",
+                    "default": 3
+                },
+                "region": {
+                    "type": "string"
+                }
+            }
+        },
+        "optional": {
+            "type": "object",
+            "properties": {
+                "name": {
+                    "type": "string",
+                    "default": "name"
+                }
+            }
+        },
+        "vms": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "size": {
+                        "type": "string",
+                        "default": "Small"
+                    }
+                }
+            }
+        }
+    }
+}
+"#;
+
+    const UNKNOWN_KEYWORD_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Unknown Keyword Schema",
+    "description": "Arbitrary object with an unknown keyword for testing",
+    "type": "object",
+    "x-version": "2023-07-28",
+    "properties": {
+        "foo": {
+            "type": "string",
+            "nullable": true
+        }
+    }
+}
+"#;
+
+    const FORMAT_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Format Schema",
+    "description": "Arbitrary object with a format for testing",
+    "type": "object",
+    "anyOf": [
+        {
+            "type": "object",
+            "properties": {
+                "contact": {
+                    "type": "string",
+                    "format": "email"
+                }
+            }
+        }
+    ]
+}
+"#;
+
+    const COERCION_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Coercion Schema",
+    "description": "Arbitrary object with scalar types for testing",
+    "type": "object",
+    "properties": {
+        "nonce": {
+            "type": "string"
+        },
+        "vmId": {
+            "type": "string"
+        },
+        "foo2": {
             "type": "string"
+        },
+        "count": {
+            "type": "integer"
+        },
+        "ratio": {
+            "type": "number"
+        },
+        "enabled": {
+            "type": "boolean"
+        },
+        "size": {
+            "type": "integer"
+        }
+    }
+}
+"#;
+
+    const RENAME_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Rename Schema",
+    "description": "Arbitrary object with renamed properties for testing",
+    "type": "object",
+    "properties": {
+        "nonce": {
+            "type": "integer"
+        },
+        "virtualMachineId": {
+            "type": "string",
+            "x-source-name": "vmId",
+            "x-aliases": ["vm_id"]
+        },
+        "timestamp": {
+            "type": "object",
+            "properties": {
+                "createdOn": {
+                    "type": "string",
+                    "x-aliases": ["created"]
+                },
+                "expiresOn": {
+                    "type": "string"
+                }
+            }
+        }
+    }
+}
+"#;
+
+    const RENAME_SCHEMA_REPEATED_ALIAS: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Rename Schema",
+    "description": "Arbitrary object with a source repeated in the aliases for testing",
+    "type": "object",
+    "properties": {
+        "new": {
+            "type": "string",
+            "x-source-name": "old",
+            "x-aliases": ["older", "old"]
+        }
+    }
+}
+"#;
+
+    const INVALID_SCHEMA_RENAME_CONFLICT_ALL_OF: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Invalid Schema",
+    "description": "Two properties of allOf branches renamed from the same property",
+    "type": "object",
+    "allOf": [
+        {
+            "properties": {
+                "a": {
+                    "x-source-name": "old"
+                }
+            }
+        },
+        {
+            "properties": {
+                "b": {
+                    "x-aliases": ["old"]
+                }
+            }
+        }
+    ]
+}
+"#;
+
+    const INVALID_SCHEMA_RENAME_CONFLICT: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Invalid Schema",
+    "description": "Two properties renamed from the same property",
+    "type": "object",
+    "properties": {
+        "virtualMachineId": {
+            "type": "string",
+            "x-source-name": "vmId"
+        },
+        "id": {
+            "type": "string",
+            "x-aliases": ["vmId"]
+        }
+    }
+}
+"#;
+
+    const MOVE_SCHEMA: &str = r#"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Move Schema",
+    "description": "Arbitrary object with properties moved from the nested schema for testing",
+    "type": "object",
+    "properties": {
+        "nonce": {
+            "type": "integer"
+        },
+        "createdOn": {
+            "type": "string",
+            "x-source": "/timestamp/createdOn"
+        },
+        "expiresOn": {
+            "type": "string",
+            "x-source": "/timestamp/expiresOn"
+        },
+        "metadata": {
+            "type": "object",
+            "x-source": "/timestamp",
+            "properties": {
+                "nonce": {
+                    "type": "integer",
+                    "x-source": "/nonce"
+                }
+            }
         }
     }
 }
 "#;
 
+    #[test]
+    pub fn mask_json_enum_map_schema_invalid_replacement() {
+        let schemas = [
+            (
+                ENUM_MAP_SCHEMA.replace(r#""Cycling": "Off""#, r#""Cycling": "Of""#),
+                "/properties/power/x-enum-map/Cycling",
+            ),
+            (
+                ENUM_MAP_SCHEMA
+                    .replace(r#""x-enum-fallback": "Unknown""#, r#""x-enum-fallback": 0"#),
+                "/definitions/Status/x-enum-fallback",
+            ),
+            (
+                ENUM_MAP_SCHEMA.replace(r#""enum": ["On", "Off"],"#, ""),
+                "/properties/power",
+            ),
+        ];
+
+        for (schema, pointer) in schemas {
+            assert!(matches!(
+                from_str(&schema),
+                Err(ParseError::InvalidJsonSchema(message)) if message.starts_with(pointer)
+            ));
+        }
+    }
+
+    const ENUM_MAP_SCHEMA: &str = r##"
+{
+    "$schema": "http://json-schema.org/draft-07/schema",
+    "title": "Enum Map Schema",
+    "description": "Arbitrary object with enums mapped to legacy values for testing",
+    "type": "object",
+    "definitions": {
+        "Status": {
+            "type": "string",
+            "enum": ["Running", "Stopped", "Unknown"],
+            "x-enum-map": {
+                "Hibernated": "Stopped",
+                "Deallocated": "Stopped"
+            },
+            "x-enum-fallback": "Unknown"
+        }
+    },
+    "properties": {
+        "status": {
+            "$ref": "#/definitions/Status"
+        },
+        "power": {
+            "enum": ["On", "Off"],
+            "x-enum-map": {
+                "Cycling": "Off"
+            }
+        },
+        "vms": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "status": {
+                        "$ref": "#/definitions/Status"
+                    }
+                }
+            }
+        }
+    }
+}
+"##;
+
     const INVALID_SCHEMA_OBJECT: &str = r#"
 {
     "$schema": "http://json-schema.org/draft-04/schema",